@@ -1,20 +1,149 @@
 use anyhow::{Context, Result};
 use log::{debug, error, info, warn};
-use std::process::Stdio;
-use tokio::process::Command;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tempfile::TempPath;
+use rhai::{Array, Dynamic, Engine, Map, Scope};
 
-use crate::config::{ActionConfig, ActionType};
+use crate::assistant::{chat_request, completions_url, parse_reply, DEFAULT_ENDPOINT, DEFAULT_MODEL};
+use crate::clipboard;
+use crate::config::{ActionConfig, ActionParameters, ActionType, BodyFormat, HttpMethod, MqttTrigger, OnFailure, OutputTarget, PipelineStep};
+use crate::keys::{parse_keys, split_at_delays, KeyEvent};
+use crate::mqtt::MqttClient;
+use crate::notification;
+use crate::output::{self, CommandOutput, DEFAULT_OUTPUT_LIMIT};
+use crate::plugin::{self, PluginRequest, PluginStatus};
+use crate::process::{self, ProcessTracker, DEFAULT_KILL_AFTER_MS};
+use crate::scripting::{self, script_error, ScriptResult};
+use crate::state::ActionState;
+use crate::template::{expand_parameters, TemplateContext};
+#[cfg(target_os = "linux")]
+use crate::freedesktop::{Notification, Notifier};
+#[cfg(target_os = "linux")]
+use crate::keys::{xdotool_args, ydotool_args};
 
+// Webhook requests give up after this long unless `timeout_ms` is set
+const WEBHOOK_TIMEOUT_MS: u64 = 10_000;
+// Models can take a while to answer, local ones in particular
+const AI_TIMEOUT_MS: u64 = 60_000;
+// Commands whose output is captured are killed after this long unless
+// `timeout_ms` is set
+const CAPTURE_TIMEOUT_MS: u64 = 60_000;
+
+// Plugins are killed after this long unless `timeout_ms` is set
+const PLUGIN_TIMEOUT_MS: u64 = 30_000;
+// Longer plugin responses are cut off, which makes them invalid
+const PLUGIN_OUTPUT_LIMIT: usize = 64 * 1024;
+
+// Runs inline scripts that don't name an interpreter
+#[cfg(windows)]
+const DEFAULT_INTERPRETER: &str = "powershell";
+#[cfg(not(windows))]
+const DEFAULT_INTERPRETER: &str = "sh";
+
+#[derive(Clone)]
 pub struct ActionExecutor {
-    // Future: Could hold state for more complex actions
+    // When set, action names are recorded here instead of being executed
+    #[cfg(test)]
+    recorder: Option<Arc<Mutex<Vec<String>>>>,
+    state: Arc<ActionState>,
+    // Processes started by toggle actions, by action name
+    toggled: Arc<tokio::sync::Mutex<HashMap<String, Child>>>,
+    // Commands still running, stopped at shutdown
+    processes: ProcessTracker,
+    // Names of the actions behind clicked notification buttons
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    clicks: mpsc::UnboundedSender<String>,
+    clicked: Arc<Mutex<Option<mpsc::UnboundedReceiver<String>>>>,
+    // Connection to the desktop's notification service, made on first use
+    #[cfg(target_os = "linux")]
+    notifier: Arc<tokio::sync::OnceCell<Notifier>>,
+    // Broker connection for MqttPublish actions, when configured
+    mqtt: Option<MqttClient>,
 }
 
 impl ActionExecutor {
     pub fn new() -> Self {
-        ActionExecutor {}
+        Self::with_state(ActionState::in_memory())
+    }
+
+    /// Create an executor that keeps toggle states in `state`
+    pub fn with_state(state: ActionState) -> Self {
+        let (clicks, clicked) = mpsc::unbounded_channel();
+        ActionExecutor {
+            #[cfg(test)]
+            recorder: None,
+            state: Arc::new(state),
+            toggled: Arc::default(),
+            processes: ProcessTracker::new(),
+            clicks,
+            clicked: Arc::new(Mutex::new(Some(clicked))),
+            #[cfg(target_os = "linux")]
+            notifier: Arc::default(),
+            mqtt: None,
+        }
+    }
+
+    /// Publish MqttPublish actions through `mqtt`
+    pub fn with_mqtt(self, mqtt: MqttClient) -> Self {
+        ActionExecutor {
+            mqtt: Some(mqtt),
+            ..self
+        }
+    }
+
+    /// Create an executor that only records which actions would run.
+    /// Used to test the report-to-action path without side effects.
+    #[cfg(test)]
+    pub fn recording(recorder: Arc<Mutex<Vec<String>>>) -> Self {
+        ActionExecutor {
+            recorder: Some(recorder),
+            ..Self::new()
+        }
     }
 
-    pub async fn execute_action(&self, action: &ActionConfig) -> Result<()> {
+    /// Names of the actions to run because their notification button was
+    /// clicked. Only the first call gets them.
+    pub fn take_notification_clicks(&self) -> Option<mpsc::UnboundedReceiver<String>> {
+        self.clicked.lock().unwrap().take()
+    }
+
+    /// MQTT triggers whose topic received a message. Only the first call
+    /// gets them.
+    pub fn take_mqtt_triggers(&self) -> Option<mpsc::UnboundedReceiver<MqttTrigger>> {
+        self.mqtt.as_ref().and_then(|mqtt| mqtt.take_triggers())
+    }
+
+    /// Stop the commands that actions started and are still running,
+    /// including the processes of toggles that are on
+    pub async fn shutdown(&self) {
+        let stop_toggled = async {
+            let mut toggled = self.toggled.lock().await;
+            for (name, mut child) in toggled.drain() {
+                info!("Stopping {}", name);
+                if let Err(e) = process::terminate(&mut child, Duration::from_millis(DEFAULT_KILL_AFTER_MS)).await {
+                    warn!("Failed to stop {}: {}", name, e);
+                }
+            }
+        };
+        tokio::join!(stop_toggled, self.processes.shutdown());
+
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.disconnect();
+        }
+    }
+
+    pub async fn execute_action(&self, action: &ActionConfig, context: &TemplateContext) -> Result<()> {
         if !action.enabled {
             debug!("Action '{}' is disabled, skipping", action.name);
             return Ok(());
@@ -22,24 +151,384 @@ impl ActionExecutor {
 
         info!("Executing action: {}", action.name);
 
+        #[cfg(test)]
+        if let Some(recorder) = &self.recorder {
+            recorder.lock().unwrap().push(action.name.clone());
+            return Ok(());
+        }
+
         match action.action_type {
+            ActionType::Pipeline => self.run_pipeline(action, context).await,
+            ActionType::Toggle => self.toggle(action, context).await,
+            ActionType::Cycle => self.run_cycle(action, context).await,
+            _ => self.run(&action.action_type, &action.parameters, false, context).await,
+        }
+    }
+
+    /// Run a single action. With `wait`, a command is waited for.
+    async fn run(&self, action_type: &ActionType, parameters: &ActionParameters, wait: bool, context: &TemplateContext) -> Result<()> {
+        let parameters = &expand_parameters(parameters, context)?;
+
+        match action_type {
             ActionType::OpenUrl => {
-                self.open_url(action).await
+                self.open_url(parameters).await
             }
             ActionType::RunCommand => {
-                self.run_command(action).await
+                self.run_command(parameters, wait, context).await
+            }
+            ActionType::RunScript => {
+                self.run_script(parameters, wait, context).await
             }
             ActionType::SendKeys => {
-                self.send_keys(action).await
+                self.send_keys(parameters).await
             }
             ActionType::ShowNotification => {
-                self.show_notification(action).await
+                self.show_notification(parameters).await
+            }
+            ActionType::Webhook => {
+                self.send_webhook(parameters, context).await
+            }
+            ActionType::AskAi => {
+                self.ask_ai(parameters).await
+            }
+            ActionType::Plugin => {
+                self.run_plugin(parameters, context).await
+            }
+            ActionType::RhaiScript => {
+                self.run_rhai(parameters, context).await
+            }
+            ActionType::MqttPublish => {
+                self.publish_mqtt(parameters, context).await
+            }
+            ActionType::Pipeline => {
+                Err(anyhow::anyhow!("Pipelines cannot be nested, use a Parallel step"))
+            }
+            ActionType::Toggle => {
+                Err(anyhow::anyhow!("Toggle actions cannot be pipeline steps"))
+            }
+            ActionType::Cycle => {
+                Err(anyhow::anyhow!("Cycle actions cannot be pipeline steps"))
+            }
+        }
+    }
+
+    async fn run_pipeline(&self, action: &ActionConfig, context: &TemplateContext) -> Result<()> {
+        if action.steps.is_empty() {
+            return Err(anyhow::anyhow!("Steps are required for Pipeline action"));
+        }
+
+        for (index, step) in action.steps.iter().enumerate() {
+            if let Err(e) = self.run_step(step, context).await {
+                match action.on_failure {
+                    OnFailure::Stop => {
+                        return Err(e.context(format!("Step {} failed, skipping the remaining steps", index + 1)));
+                    }
+                    OnFailure::Continue => {
+                        warn!("Step {} of {} failed: {:#}", index + 1, action.name, e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_cycle(&self, action: &ActionConfig, context: &TemplateContext) -> Result<()> {
+        if action.steps.is_empty() {
+            return Err(anyhow::anyhow!("Steps are required for Cycle action"));
+        }
+
+        let reset_after = action.reset_after_ms.map(Duration::from_millis);
+        let index = self.state.advance_cycle(&action.name, action.steps.len(), action.persist, reset_after);
+        info!("Running entry {} of {} in {}", index + 1, action.steps.len(), action.name);
+
+        self.run_step(&action.steps[index], context).await
+    }
+
+    async fn toggle(&self, action: &ActionConfig, context: &TemplateContext) -> Result<()> {
+        let on = self.switch(action, context).await?;
+        let state = if on { "on" } else { "off" };
+        info!("{} is now {}", action.name, state);
+
+        // The switch already happened, so a missing notification is no error
+        let title = action.parameters.title.as_deref().unwrap_or("SVBONY AI Assistant");
+        if let Err(e) = self.show_system_notification(title, &format!("{} is now {}", action.name, state)).await {
+            warn!("Failed to show toggle state: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Flip a toggle action and return whether it is now on
+    async fn switch(&self, action: &ActionConfig, context: &TemplateContext) -> Result<bool> {
+        let parameters = expand_parameters(&action.parameters, context)?;
+        let command = parameters.command.as_ref()
+            .context("Command parameter is required for Toggle action")?;
+
+        // With a stop command, the start and stop commands take turns
+        if let Some(stop_command) = &parameters.stop_command {
+            let on = !self.state.is_on(&action.name);
+            let step = if on {
+                parameters.clone()
+            } else {
+                ActionParameters {
+                    command: Some(stop_command.clone()),
+                    args: parameters.stop_args.clone(),
+                    ..ActionParameters::default()
+                }
+            };
+            self.run_command(&step, true, context).await?;
+            self.state.set_on(&action.name, on);
+            return Ok(on);
+        }
+
+        // Otherwise the command runs while the toggle is on
+        let mut toggled = self.toggled.lock().await;
+        if let Some(mut child) = toggled.remove(&action.name) {
+            if child.try_wait()?.is_none() {
+                info!("Stopping command: {}", command);
+                process::terminate(&mut child, kill_after(&parameters)).await
+                    .context("Failed to stop process")?;
+                return Ok(false);
             }
+            debug!("{} exited on its own, starting it again", command);
         }
+
+        let args = parameters.args.clone().unwrap_or_default();
+        info!("Starting command: {} {:?}", command, args);
+        let mut cmd = Command::new(command);
+        cmd.args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        process::new_group(&mut cmd);
+        let child = cmd.spawn()
+            .with_context(|| format!("Failed to run command {}", command))?;
+        toggled.insert(action.name.clone(), child);
+        Ok(true)
     }
 
-    async fn open_url(&self, action: &ActionConfig) -> Result<()> {
-        let url = action.parameters.url.as_ref()
+    fn run_step<'a>(&'a self, step: &'a PipelineStep, context: &'a TemplateContext) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            match step {
+                PipelineStep::Action { name, action_type, parameters, wait } => {
+                    if let Some(name) = name {
+                        info!("Running step: {}", name);
+                    }
+                    self.run(action_type, parameters, *wait, context).await
+                }
+                PipelineStep::Parallel(steps) => {
+                    let mut group = JoinSet::new();
+                    for step in steps {
+                        let executor = self.clone();
+                        let step = step.clone();
+                        let context = context.clone();
+                        group.spawn(async move { executor.run_step(&step, &context).await });
+                    }
+
+                    let mut failures = 0;
+                    while let Some(result) = group.join_next().await {
+                        if let Err(e) = result.map_err(anyhow::Error::from).and_then(|result| result) {
+                            error!("Parallel step failed: {:#}", e);
+                            failures += 1;
+                        }
+                    }
+
+                    match failures {
+                        0 => Ok(()),
+                        _ => Err(anyhow::anyhow!("{} of {} parallel steps failed", failures, steps.len())),
+                    }
+                }
+                PipelineStep::Delay { ms } => {
+                    debug!("Waiting {} ms", ms);
+                    tokio::time::sleep(Duration::from_millis(*ms)).await;
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    /// Run a plugin with the event and carry out the requests it returns
+    async fn run_plugin(&self, parameters: &ActionParameters, context: &TemplateContext) -> Result<()> {
+        let name = parameters.plugin.as_ref()
+            .context("Plugin parameter is required for Plugin action")?;
+        let path = plugin::find(&plugin::plugins_dir()?, name)?;
+        let request = plugin::request(context.event_json(), parameters.plugin_options.as_ref());
+
+        info!("Running plugin {} ({})", name, path.display());
+
+        let mut cmd = Command::new(&path);
+        cmd.args(parameters.args.as_deref().unwrap_or_default());
+        cmd.envs(context.env_vars());
+        hide_console(&mut cmd, false);
+
+        let limit = parameters.max_output_bytes.unwrap_or(PLUGIN_OUTPUT_LIMIT);
+        let timeout = Duration::from_millis(parameters.timeout_ms.unwrap_or(PLUGIN_TIMEOUT_MS));
+        let output = output::capture(&mut cmd, Some(request.to_string().as_bytes()), &self.processes, limit, timeout, kill_after(parameters)).await
+            .with_context(|| format!("Failed to run plugin {}", name))?;
+        if !output.stderr.trim().is_empty() {
+            info!("Plugin {}: {}", name, output.stderr.trim());
+        }
+
+        let response = match plugin::parse_response(&output.stdout) {
+            Ok(response) => response,
+            // A plugin that crashed usually says why on its error output
+            Err(_) if !output.status.success() => {
+                return Err(anyhow::anyhow!("Plugin {} exited with status {}: {}", name, output.status, output.summary()));
+            }
+            Err(e) => return Err(e.context(format!("Plugin {} failed", name))),
+        };
+
+        // Requests are carried out for failures as well, so that a plugin
+        // can explain what went wrong
+        for request in &response.requests {
+            self.carry_out_plugin_request(request).await
+                .with_context(|| format!("Request of plugin {} failed", name))?;
+        }
+
+        match response.status {
+            PluginStatus::Ok => {
+                debug!("Plugin {} succeeded: {}", name, response.message.as_deref().unwrap_or(""));
+                Ok(())
+            }
+            PluginStatus::Error => Err(anyhow::anyhow!(
+                "Plugin {} failed: {}", name, response.message.as_deref().unwrap_or("no reason given")
+            )),
+        }
+    }
+
+    /// Carry out a plugin's follow-up request. Its text is used as it is,
+    /// without placeholder expansion.
+    async fn carry_out_plugin_request(&self, request: &PluginRequest) -> Result<()> {
+        match request {
+            PluginRequest::Notify { title, message } => {
+                self.show_system_notification(title.as_deref().unwrap_or("SVBONY AI Assistant"), message).await
+            }
+            PluginRequest::OpenUrl { url } => {
+                self.open_url(&ActionParameters { url: Some(url.clone()), ..ActionParameters::default() }).await
+            }
+            PluginRequest::Copy { text } => {
+                clipboard::write_text(text).await
+            }
+            PluginRequest::SendKeys { keys } => {
+                self.send_keys(&ActionParameters { keys: Some(keys.clone()), ..ActionParameters::default() }).await
+            }
+        }
+    }
+
+    /// Run a Rhai script. It runs on a blocking thread and calls back into
+    /// the executor for the assistant's functions.
+    async fn run_rhai(&self, parameters: &ActionParameters, context: &TemplateContext) -> Result<()> {
+        let script = parameters.script.clone()
+            .context("Script parameter is required for RhaiScript action")?;
+        let max_operations = parameters.max_operations.unwrap_or(scripting::DEFAULT_MAX_OPERATIONS);
+        let timeout = Duration::from_millis(parameters.timeout_ms.unwrap_or(scripting::DEFAULT_SCRIPT_TIMEOUT_MS));
+
+        info!("Running Rhai script");
+
+        let executor = self.clone();
+        let context = context.clone();
+        let runtime = tokio::runtime::Handle::current();
+        // Script values can't leave the thread, so only their text does
+        let result = tokio::task::spawn_blocking(move || {
+            let engine = executor.script_engine(&runtime, &context, max_operations, timeout);
+            let mut scope = Scope::new();
+            scripting::event_map(&context)
+                .and_then(|event| {
+                    scope.push_constant("event", event);
+                    engine.eval_with_scope::<Dynamic>(&mut scope, &script)
+                })
+                .map(|value| value.to_string())
+                .map_err(|e| scripting::describe_error(&e, timeout))
+        }).await.context("Script stopped unexpectedly")?;
+
+        let value = result.map_err(|e| anyhow::anyhow!(e))?;
+        debug!("Script returned {}", value);
+        Ok(())
+    }
+
+    /// Engine with the assistant's functions, which run on `runtime`.
+    /// Commands and requests are cut short when the script runs out of time.
+    fn script_engine(&self, runtime: &tokio::runtime::Handle, context: &TemplateContext, max_operations: u64, timeout: Duration) -> Engine {
+        let mut engine = scripting::engine(max_operations, timeout);
+        let deadline = Instant::now() + timeout;
+
+        let (executor, handle) = (self.clone(), runtime.clone());
+        engine.register_fn("open_url", move |url: &str| -> ScriptResult<()> {
+            let parameters = ActionParameters { url: Some(url.to_string()), ..ActionParameters::default() };
+            handle.block_on(executor.open_url(&parameters)).map_err(script_error)
+        });
+
+        let (executor, handle) = (self.clone(), runtime.clone());
+        engine.register_fn("notify", move |title: &str, message: &str| -> ScriptResult<()> {
+            handle.block_on(executor.show_system_notification(title, message)).map_err(script_error)
+        });
+        let (executor, handle) = (self.clone(), runtime.clone());
+        engine.register_fn("notify", move |message: &str| -> ScriptResult<()> {
+            handle.block_on(executor.show_system_notification("SVBONY AI Assistant", message)).map_err(script_error)
+        });
+
+        let (executor, handle) = (self.clone(), runtime.clone());
+        engine.register_fn("send_keys", move |keys: &str| -> ScriptResult<()> {
+            let parameters = ActionParameters { keys: Some(keys.to_string()), ..ActionParameters::default() };
+            handle.block_on(executor.send_keys(&parameters)).map_err(script_error)
+        });
+
+        let (executor, handle, event) = (self.clone(), runtime.clone(), context.clone());
+        engine.register_fn("run_command", move |command: &str, args: Array| -> ScriptResult<Map> {
+            let args = args.into_iter().map(|arg| arg.to_string()).collect();
+            let parameters = script_command_parameters(command, Some(args), scripting::time_left(deadline)?);
+            handle.block_on(executor.script_command(&parameters, &event)).map_err(script_error)
+        });
+        let (executor, handle, event) = (self.clone(), runtime.clone(), context.clone());
+        engine.register_fn("run_command", move |command: &str| -> ScriptResult<Map> {
+            let parameters = script_command_parameters(command, None, scripting::time_left(deadline)?);
+            handle.block_on(executor.script_command(&parameters, &event)).map_err(script_error)
+        });
+
+        let handle = runtime.clone();
+        engine.register_fn("http_get", move |url: &str| -> ScriptResult<Map> {
+            let timeout = scripting::time_left(deadline)?;
+            handle.block_on(script_request(reqwest::Method::GET, url, None, timeout)).map_err(script_error)
+        });
+        let handle = runtime.clone();
+        engine.register_fn("http_post", move |url: &str, body: Dynamic| -> ScriptResult<Map> {
+            let body = scripting::to_json(&body)?;
+            let timeout = scripting::time_left(deadline)?;
+            handle.block_on(script_request(reqwest::Method::POST, url, Some(body), timeout)).map_err(script_error)
+        });
+
+        let state = self.state.clone();
+        engine.register_fn("store_get", move |key: &str| -> ScriptResult<Dynamic> {
+            match state.stored(key) {
+                Some(value) => scripting::to_dynamic(&value),
+                None => Ok(Dynamic::UNIT),
+            }
+        });
+        let state = self.state.clone();
+        engine.register_fn("store_set", move |key: &str, value: Dynamic| -> ScriptResult<()> {
+            state.store(key, scripting::to_json(&value)?);
+            Ok(())
+        });
+
+        engine
+    }
+
+    /// Run a command for a script and return its status and output
+    async fn script_command(&self, parameters: &ActionParameters, context: &TemplateContext) -> Result<Map> {
+        let (mut cmd, command) = prepare_command(parameters, context)?;
+        hide_console(&mut cmd, false);
+        let output = self.capture_output(&mut cmd, parameters).await
+            .with_context(|| format!("Failed to run command {}", command))?;
+
+        let mut result = Map::new();
+        result.insert("status".into(), output.status.code().map_or(Dynamic::UNIT, |code| Dynamic::from_int(code.into())));
+        result.insert("stdout".into(), output.stdout.into());
+        result.insert("stderr".into(), output.stderr.into());
+        Ok(result)
+    }
+
+    async fn open_url(&self, parameters: &ActionParameters) -> Result<()> {
+        let url = parameters.url.as_ref()
             .context("URL parameter is required for OpenUrl action")?;
 
         info!("Opening URL: {}", url);
@@ -57,34 +546,77 @@ impl ActionExecutor {
         }
     }
 
-    async fn run_command(&self, action: &ActionConfig) -> Result<()> {
-        let command = action.parameters.command.as_ref()
-            .context("Command parameter is required for RunCommand action")?;
+    async fn run_command(&self, parameters: &ActionParameters, wait: bool, context: &TemplateContext) -> Result<()> {
+        let (cmd, command) = prepare_command(parameters, context)?;
+        self.run_process(cmd, command, parameters, wait, None).await
+    }
 
-        let args = action.parameters.args.as_ref().cloned().unwrap_or_default();
+    async fn run_script(&self, parameters: &ActionParameters, wait: bool, context: &TemplateContext) -> Result<()> {
+        let script = parameters.script.as_ref()
+            .context("Script parameter is required for RunScript action")?;
+        let interpreter = parameters.interpreter.as_deref().unwrap_or(DEFAULT_INTERPRETER);
 
-        info!("Running command: {} {:?}", command, args);
+        // The file is removed once the interpreter is done with it
+        let script_file = write_script(script, interpreter)?;
+        let args = parameters.args.as_ref().cloned().unwrap_or_default();
 
-        let mut cmd = Command::new(command);
+        info!("Running {} script {}", interpreter, script_file.display());
+
+        let mut cmd = Command::new(interpreter);
+        cmd.args(interpreter_args(interpreter));
+        cmd.arg(&*script_file);
         cmd.args(&args);
+        cmd.envs(context.env_vars());
+        self.run_process(cmd, interpreter, parameters, wait, Some(script_file)).await
+    }
+
+    /// Run a prepared command, in the background unless `wait` is set or its
+    /// output is needed. `script_file` is kept until the command exits.
+    async fn run_process(&self, mut cmd: Command, command: &str, parameters: &ActionParameters, wait: bool, script_file: Option<TempPath>) -> Result<()> {
+        let captured = parameters.output.is_some() || parameters.notify_on_error.unwrap_or(false);
+        let detach = parameters.detach.unwrap_or(false);
+        if detach && captured {
+            return Err(anyhow::anyhow!(
+                "Command {} can't be detached, output and notify_on_error wait for it to finish", command
+            ));
+        }
+
         cmd.stdout(Stdio::null());
         cmd.stderr(Stdio::null());
 
-        // Windows specific: Hide console window
-        #[cfg(target_os = "windows")]
-        {
-            
-            const CREATE_NO_WINDOW: u32 = 0x08000000;
-            const DETACHED_PROCESS: u32 = 0x00000008;
-            cmd.creation_flags(CREATE_NO_WINDOW | DETACHED_PROCESS);
+        // Detached commands are left alone: no timeout, and they keep running
+        // when the assistant exits
+        let processes = if detach {
+            None
+        } else {
+            process::new_group(&mut cmd);
+            Some(self.processes.clone())
+        };
+        let timeout = parameters.timeout_ms.map(Duration::from_millis);
+        let kill_after = kill_after(parameters);
+
+        hide_console(&mut cmd, detach);
+
+        if captured {
+            return self.run_captured(&mut cmd, command, parameters).await;
         }
 
         match cmd.spawn() {
+            Ok(mut child) if wait => {
+                let status = wait_for_child(processes, &mut child, timeout, kill_after).await
+                    .with_context(|| format!("Error waiting for command {}", command))?;
+                if !status.success() {
+                    return Err(anyhow::anyhow!("Command {} exited with status: {}", command, status));
+                }
+                debug!("Command completed successfully");
+                Ok(())
+            }
             Ok(mut child) => {
                 // Don't wait for the process to complete for most commands
                 // This allows opening applications without blocking
+                let command = command.to_string();
                 tokio::spawn(async move {
-                    match child.wait().await {
+                    match wait_for_child(processes, &mut child, timeout, kill_after).await {
                         Ok(status) => {
                             if status.success() {
                                 debug!("Command completed successfully");
@@ -93,9 +625,10 @@ impl ActionExecutor {
                             }
                         }
                         Err(e) => {
-                            error!("Error waiting for command: {}", e);
+                            error!("Error waiting for command {}: {:#}", command, e);
                         }
                     }
+                    drop(script_file);
                 });
 
                 Ok(())
@@ -107,34 +640,245 @@ impl ActionExecutor {
         }
     }
 
-    async fn send_keys(&self, action: &ActionConfig) -> Result<()> {
-        let keys = action.parameters.keys.as_ref()
+    /// Run a command to completion and deliver what it printed
+    async fn run_captured(&self, cmd: &mut Command, command: &str, parameters: &ActionParameters) -> Result<()> {
+        let title = parameters.title.as_deref().unwrap_or(command);
+
+        let result = match self.capture_output(cmd, parameters).await {
+            Ok(output) if output.status.success() => Ok(output),
+            Ok(output) => Err(anyhow::anyhow!("Command {} exited with status {}: {}", command, output.status, output.summary())),
+            Err(e) => Err(e.context(format!("Failed to run command {}", command))),
+        };
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                if parameters.notify_on_error.unwrap_or(false) {
+                    let message = notification_text(&format!("{:#}", e));
+                    if let Err(notify_error) = self.show_system_notification(&format!("{} failed", title), &message).await {
+                        warn!("Failed to show command error: {}", notify_error);
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        let summary = output.summary();
+        debug!("Command printed: {}", summary);
+
+        match parameters.output {
+            Some(OutputTarget::Notification) => {
+                let message = if summary.is_empty() { "Finished without output".to_string() } else { notification_text(&summary) };
+                self.show_system_notification(title, &message).await
+            }
+            Some(OutputTarget::File) => {
+                let path = parameters.output_file.as_ref()
+                    .context("Output file parameter is required to write command output to a file")?;
+                tokio::fs::write(path, &output.stdout).await
+                    .with_context(|| format!("Failed to write command output to {}", path))?;
+                info!("Command output written to {}", path);
+                Ok(())
+            }
+            Some(OutputTarget::Clipboard) => {
+                clipboard::write_text(&summary).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Run a command to completion within the limits `parameters` set
+    async fn capture_output(&self, cmd: &mut Command, parameters: &ActionParameters) -> Result<CommandOutput> {
+        let limit = parameters.max_output_bytes.unwrap_or(DEFAULT_OUTPUT_LIMIT);
+        let timeout = Duration::from_millis(parameters.timeout_ms.unwrap_or(CAPTURE_TIMEOUT_MS));
+        output::capture(cmd, None, &self.processes, limit, timeout, kill_after(parameters)).await
+    }
+
+    async fn send_keys(&self, parameters: &ActionParameters) -> Result<()> {
+        let keys = parameters.keys.as_ref()
             .context("Keys parameter is required for SendKeys action")?;
 
+        let events = parse_keys(keys)
+            .with_context(|| format!("Invalid key sequence '{}'", keys))?;
+
         info!("Sending keys: {}", keys);
 
-        // This is a placeholder implementation
-        // In a real implementation, you would use platform-specific APIs
-        // or a cross-platform crate like `enigo` for key simulation
-        warn!("SendKeys action is not yet implemented");
-        
-        // For now, just log what keys would be sent
-        info!("Would send keys: {}", keys);
-        
+        for (batch, delay) in split_at_delays(&events) {
+            if !batch.is_empty() {
+                self.send_key_events(&batch).await?;
+            }
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn send_key_events(&self, events: &[KeyEvent]) -> Result<()> {
+        // xdotool only reaches X11 clients; ydotool goes through uinput and
+        // also works under Wayland
+        let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+        let (tool, args) = if wayland {
+            ("ydotool", ydotool_args(events)?)
+        } else {
+            ("xdotool", xdotool_args(events))
+        };
+
+        debug!("Running {} {:?}", tool, args);
+
+        let output = Command::new(tool)
+            .args(&args)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {}, is it installed?", tool))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("{} exited with {}: {}", tool, output.status, stderr.trim()));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn send_key_events(&self, events: &[KeyEvent]) -> Result<()> {
+        warn!("SendKeys action is only implemented on Linux");
+        debug!("Would send key events: {:?}", events);
+        Ok(())
+    }
+
+    async fn send_webhook(&self, parameters: &ActionParameters, context: &TemplateContext) -> Result<()> {
+        let url = parameters.url.as_ref()
+            .context("URL parameter is required for Webhook action")?;
+        let method = parameters.method.unwrap_or_default();
+        let timeout = Duration::from_millis(parameters.timeout_ms.unwrap_or(WEBHOOK_TIMEOUT_MS));
+
+        info!("Sending {} request to {}", method.as_str(), url);
+
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to create HTTP client")?;
+        let mut request = match method {
+            HttpMethod::Get => client.get(url),
+            HttpMethod::Post => client.post(url),
+            HttpMethod::Put => client.put(url),
+        };
+        for (name, value) in parameters.headers.iter().flatten() {
+            request = request.header(name, value);
+        }
+
+        if method != HttpMethod::Get {
+            let body = parameters.body.clone().unwrap_or_else(|| context.event_json());
+            request = match parameters.body_format.unwrap_or_default() {
+                BodyFormat::Json => request.json(&body),
+                BodyFormat::Form => request.form(&form_fields(&body)?),
+            };
+        }
+
+        let response = request.send().await
+            .with_context(|| format!("Request to {} failed", url))?;
+        let status = response.status();
+        let accepted = match &parameters.success_status {
+            Some(accepted) => accepted.contains(&status.as_u16()),
+            None => status.is_success(),
+        };
+
+        if !accepted {
+            let text = response.text().await.unwrap_or_default();
+            let text: String = text.chars().take(200).collect();
+            return Err(anyhow::anyhow!("{} answered with status {}: {}", url, status, text.trim()));
+        }
+
+        info!("Request to {} succeeded with status {}", url, status);
         Ok(())
     }
 
-    async fn show_notification(&self, action: &ActionConfig) -> Result<()> {
+    async fn publish_mqtt(&self, parameters: &ActionParameters, context: &TemplateContext) -> Result<()> {
+        let topic = parameters.topic.as_ref()
+            .context("Topic parameter is required for MqttPublish action")?;
+        let mqtt = self.mqtt.as_ref()
+            .context("MqttPublish actions need the mqtt settings")?;
+        let qos = parameters.qos.unwrap_or(0);
+        let qos = rumqttc::qos(qos)
+            .map_err(|_| anyhow::anyhow!("QoS {} is not 0, 1 or 2", qos))?;
+        let payload = parameters.payload.clone().unwrap_or_else(|| context.event_json().to_string());
+
+        info!("Publishing to {}", topic);
+        mqtt.publish(topic, payload.into_bytes(), qos, parameters.retain.unwrap_or(false))
+    }
+
+    async fn ask_ai(&self, parameters: &ActionParameters) -> Result<()> {
+        let reply = self.request_ai_reply(parameters).await?;
+        info!("AI replied with {} characters", reply.chars().count());
+
+        if parameters.copy_reply.unwrap_or(false) {
+            clipboard::write_text(&reply).await?;
+        }
+
+        let title = parameters.title.as_deref().unwrap_or("AI Assistant");
+        self.show_system_notification(title, &notification_text(&reply)).await
+    }
+
+    /// Send the prompt and input text of an AskAi action and return the reply
+    async fn request_ai_reply(&self, parameters: &ActionParameters) -> Result<String> {
+        let prompt = parameters.prompt.as_ref()
+            .context("Prompt parameter is required for AskAi action")?;
+
+        let input = match parameters.input {
+            Some(source) => {
+                let text = clipboard::read_text(source).await?;
+                if text.trim().is_empty() {
+                    return Err(anyhow::anyhow!("The {:?} is empty, nothing to ask about", source));
+                }
+                Some(text)
+            }
+            None => None,
+        };
+
+        let url = completions_url(parameters.url.as_deref().unwrap_or(DEFAULT_ENDPOINT));
+        let model = parameters.model.as_deref().unwrap_or(DEFAULT_MODEL);
+        let timeout = Duration::from_millis(parameters.timeout_ms.unwrap_or(AI_TIMEOUT_MS));
+
+        info!("Asking {} at {}", model, url);
+
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to create HTTP client")?;
+        let mut request = client.post(&url).json(&chat_request(model, prompt, input.as_deref()));
+        // Local servers usually don't need a key
+        if let Some(api_key) = parameters.api_key.as_deref().filter(|api_key| !api_key.is_empty()) {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await
+            .with_context(|| format!("Request to {} failed", url))?;
+        let status = response.status();
+        let body: Value = match response.json().await {
+            Ok(body) => body,
+            Err(_) if !status.is_success() => {
+                return Err(anyhow::anyhow!("{} answered with status {}", url, status));
+            }
+            Err(e) => return Err(anyhow::anyhow!("Invalid reply from {}: {}", url, e)),
+        };
+
+        parse_reply(&body)
+            .with_context(|| format!("{} answered with status {}", url, status))
+    }
+
+    async fn show_notification(&self, parameters: &ActionParameters) -> Result<()> {
         let default_title = "SVBONY AI Assistant".to_string();
-        let title = action.parameters.title.as_ref()
+        let title = parameters.title.as_ref()
             .unwrap_or(&default_title);
-        let message = action.parameters.message.as_ref()
+        let message = parameters.message.as_ref()
             .context("Message parameter is required for ShowNotification action")?;
 
         info!("Showing notification: {} - {}", title, message);
 
         // Cross-platform notification implementation
-        self.show_system_notification(title, message).await
+        self.show_notification_with(title, message, parameters).await
     }
 
     #[cfg(target_os = "windows")]
@@ -146,27 +890,7 @@ impl ActionExecutor {
             "-NoProfile",
             "-ExecutionPolicy", "Bypass",
             "-Command",
-            &format!(
-                "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
-                 [Windows.UI.Notifications.ToastNotification, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
-                 [Windows.Data.Xml.Dom.XmlDocument, Windows.Data.Xml.Dom.XmlDocument, ContentType = WindowsRuntime] > $null; \
-                 $APP_ID = 'SVBONY.AI.Assistant'; \
-                 $template = @'\
-<toast>\
-    <visual>\
-        <binding template=\"ToastGeneric\">\
-            <text>{}</text>\
-            <text>{}</text>\
-        </binding>\
-    </visual>\
-</toast>\
-'@; \
-                 $xml = New-Object Windows.Data.Xml.Dom.XmlDocument; \
-                 $xml.LoadXml($template); \
-                 $toast = New-Object Windows.UI.Notifications.ToastNotification $xml; \
-                 [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier($APP_ID).Show($toast)",
-                title, message
-            )
+            &notification::toast_script(title, message),
         ]);
 
         // Hide console window
@@ -204,16 +928,7 @@ impl ActionExecutor {
             "-NoProfile",
             "-ExecutionPolicy", "Bypass",
             "-Command",
-            &format!(
-                "Add-Type -AssemblyName System.Windows.Forms; \
-                 $notify = New-Object System.Windows.Forms.NotifyIcon; \
-                 $notify.Icon = [System.Drawing.SystemIcons]::Information; \
-                 $notify.Visible = $true; \
-                 $notify.ShowBalloonTip(3000, '{}', '{}', [System.Windows.Forms.ToolTipIcon]::Info); \
-                 Start-Sleep -Seconds 4; \
-                 $notify.Dispose()",
-                title, message
-            )
+            &notification::balloon_script(title, message),
         ]);
 
         cmd.stdout(Stdio::null());
@@ -242,10 +957,7 @@ impl ActionExecutor {
     #[cfg(target_os = "macos")]
     async fn show_system_notification(&self, title: &str, message: &str) -> Result<()> {
         let mut cmd = Command::new("osascript");
-        cmd.args(&[
-            "-e",
-            &format!("display notification \"{}\" with title \"{}\"", message, title)
-        ]);
+        cmd.args(notification::osascript_args(title, message));
 
         cmd.stdout(Stdio::null());
         cmd.stderr(Stdio::null());
@@ -265,10 +977,31 @@ impl ActionExecutor {
         }
     }
 
+    #[cfg(not(target_os = "linux"))]
+    async fn show_notification_with(&self, title: &str, message: &str, _parameters: &ActionParameters) -> Result<()> {
+        self.show_system_notification(title, message).await
+    }
+
     #[cfg(target_os = "linux")]
     async fn show_system_notification(&self, title: &str, message: &str) -> Result<()> {
+        self.show_notification_with(title, message, &ActionParameters::default()).await
+    }
+
+    /// Show a notification with the icon, urgency, expiry, tag and buttons
+    /// in `parameters`, falling back to notify-send without a notification
+    /// service on the session bus
+    #[cfg(target_os = "linux")]
+    async fn show_notification_with(&self, title: &str, message: &str, parameters: &ActionParameters) -> Result<()> {
+        match self.notify_over_dbus(title, message, parameters).await {
+            Ok(id) => {
+                info!("Notification {} shown successfully", id);
+                return Ok(());
+            }
+            Err(e) => debug!("Using notify-send: {:#}", e),
+        }
+
         let mut cmd = Command::new("notify-send");
-        cmd.args(&[title, message]);
+        cmd.args(notification::notify_send_args(title, message));
         cmd.stdout(Stdio::null());
         cmd.stderr(Stdio::null());
 
@@ -283,11 +1016,7 @@ impl ActionExecutor {
             Err(e) => {
                 // Fallback: try zenity
                 let mut zenity_cmd = Command::new("zenity");
-                zenity_cmd.args(&[
-                    "--info",
-                    "--title", title,
-                    "--text", message
-                ]);
+                zenity_cmd.args(notification::zenity_args(title, message));
                 zenity_cmd.stdout(Stdio::null());
                 zenity_cmd.stderr(Stdio::null());
 
@@ -307,4 +1036,865 @@ impl ActionExecutor {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[cfg(target_os = "linux")]
+    async fn notify_over_dbus(&self, title: &str, message: &str, parameters: &ActionParameters) -> Result<u32> {
+        let notifier = self.notifier
+            .get_or_try_init(|| Notifier::connect(self.clicks.clone()))
+            .await?;
+        let buttons = parameters.buttons.as_deref().unwrap_or_default();
+
+        notifier.notify(&Notification {
+            title,
+            message,
+            icon: parameters.icon.as_deref().unwrap_or(""),
+            urgency: parameters.urgency.unwrap_or_default(),
+            expire_timeout: parameters.expire_ms.map_or(-1, |ms| ms.min(i32::MAX as u32) as i32),
+            tag: parameters.tag.as_deref(),
+            buttons,
+        }).await
+    }
+}
+
+/// Send an HTTP request for a script and return the status and the body,
+/// which is parsed when it is JSON. Longer bodies than a script may hold
+/// are refused.
+async fn script_request(method: reqwest::Method, url: &str, body: Option<Value>, timeout: Duration) -> Result<Map> {
+    info!("Sending {} request to {}", method, url);
+
+    let client = reqwest::Client::builder()
+        .timeout(timeout.min(Duration::from_millis(WEBHOOK_TIMEOUT_MS)))
+        .build()
+        .context("Failed to create HTTP client")?;
+    let mut request = client.request(method, url);
+    if let Some(body) = &body {
+        request = request.json(body);
+    }
+
+    let mut response = request.send().await
+        .with_context(|| format!("Request to {} failed", url))?;
+    let status = response.status();
+    let mut answer = Vec::new();
+    while let Some(chunk) = response.chunk().await
+        .with_context(|| format!("Failed to read the answer from {}", url))?
+    {
+        if answer.len() + chunk.len() > scripting::MAX_STRING_SIZE {
+            return Err(anyhow::anyhow!("The answer from {} is longer than {} bytes", url, scripting::MAX_STRING_SIZE));
+        }
+        answer.extend_from_slice(&chunk);
+    }
+
+    let text = String::from_utf8_lossy(&answer).into_owned();
+    let body = match serde_json::from_str::<Value>(&text) {
+        Ok(json) => scripting::to_dynamic(&json).map_err(|e| anyhow::anyhow!("{}", e))?,
+        Err(_) => text.into(),
+    };
+
+    let mut result = Map::new();
+    result.insert("status".into(), Dynamic::from_int(status.as_u16().into()));
+    result.insert("body".into(), body);
+    Ok(result)
+}
+
+/// Parameters for a command a script runs, which may take no longer than
+/// `time_left`
+fn script_command_parameters(command: &str, args: Option<Vec<String>>, time_left: Duration) -> ActionParameters {
+    let timeout = time_left.min(Duration::from_millis(CAPTURE_TIMEOUT_MS));
+    ActionParameters {
+        command: Some(command.to_string()),
+        args,
+        timeout_ms: Some(timeout.as_millis().max(1) as u64),
+        ..ActionParameters::default()
+    }
+}
+
+/// The command `parameters` name, with the event in its environment
+fn prepare_command<'a>(parameters: &'a ActionParameters, context: &TemplateContext) -> Result<(Command, &'a str)> {
+    let command = parameters.command.as_ref()
+        .context("Command parameter is required for RunCommand action")?;
+    let args = parameters.args.as_deref().unwrap_or_default();
+
+    info!("Running command: {} {:?}", command, args);
+
+    let mut cmd = Command::new(command);
+    cmd.args(args);
+    cmd.envs(context.env_vars());
+    Ok((cmd, command))
+}
+
+/// Keep `cmd` from opening a console window on Windows. A `detached`
+/// command doesn't share the assistant's console either.
+fn hide_console(cmd: &mut Command, detached: bool) {
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        const DETACHED_PROCESS: u32 = 0x00000008;
+        cmd.creation_flags(if detached { CREATE_NO_WINDOW | DETACHED_PROCESS } else { CREATE_NO_WINDOW });
+    }
+    #[cfg(not(target_os = "windows"))]
+    let _ = (cmd, detached);
+}
+
+/// Write `script` to a temporary file that `interpreter` accepts
+fn write_script(script: &str, interpreter: &str) -> Result<TempPath> {
+    let mut file = tempfile::Builder::new()
+        .prefix("svbony-script-")
+        .suffix(script_suffix(interpreter))
+        .tempfile()
+        .context("Failed to create script file")?;
+    file.write_all(script.as_bytes())
+        .and_then(|_| file.flush())
+        .context("Failed to write script file")?;
+
+    // Close the file so the interpreter can open it on Windows
+    Ok(file.into_temp_path())
+}
+
+/// Interpreter name without directory and extension, lowercased
+fn interpreter_name(interpreter: &str) -> String {
+    // Split on both separators so Windows paths work on every platform
+    let file = interpreter.rsplit(['/', '\\']).next().unwrap_or(interpreter);
+    Path::new(file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Extension the interpreter needs to run a file
+fn script_suffix(interpreter: &str) -> &'static str {
+    match interpreter_name(interpreter).as_str() {
+        "powershell" | "pwsh" => ".ps1",
+        "cmd" => ".cmd",
+        name if name.starts_with("python") || name == "py" => ".py",
+        "node" => ".js",
+        _ => "",
+    }
+}
+
+/// Arguments that go before the script file
+fn interpreter_args(interpreter: &str) -> &'static [&'static str] {
+    match interpreter_name(interpreter).as_str() {
+        "powershell" | "pwsh" => &["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-File"],
+        "cmd" => &["/C"],
+        _ => &[],
+    }
+}
+
+/// Grace period between asking a command to exit and killing it
+fn kill_after(parameters: &ActionParameters) -> Duration {
+    Duration::from_millis(parameters.kill_after_ms.unwrap_or(DEFAULT_KILL_AFTER_MS))
+}
+
+/// Wait for `child`, through `processes` unless it is detached
+async fn wait_for_child(processes: Option<ProcessTracker>, child: &mut Child, timeout: Option<Duration>, kill_after: Duration) -> Result<ExitStatus> {
+    match processes {
+        Some(processes) => processes.wait(child, timeout, kill_after).await,
+        None => Ok(child.wait().await?),
+    }
+}
+
+/// Shorten an AI reply to what fits in a notification
+fn notification_text(reply: &str) -> String {
+    const MAX_CHARS: usize = 300;
+    if reply.chars().count() <= MAX_CHARS {
+        return reply.to_string();
+    }
+    let shortened: String = reply.chars().take(MAX_CHARS - 1).collect();
+    format!("{}…", shortened.trim_end())
+}
+
+/// Flatten a JSON object into form fields; nested objects use dotted names
+fn form_fields(body: &Value) -> Result<Vec<(String, String)>> {
+    fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) -> Result<()> {
+        match value {
+            Value::Object(object) => {
+                for (name, value) in object {
+                    let name = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+                    flatten(&name, value, fields)?;
+                }
+            }
+            Value::Array(_) => return Err(anyhow::anyhow!("Form field '{}' cannot be a list", prefix)),
+            Value::Null => fields.push((prefix.to_string(), String::new())),
+            Value::String(text) => fields.push((prefix.to_string(), text.clone())),
+            other => fields.push((prefix.to_string(), other.to_string())),
+        }
+        Ok(())
+    }
+
+    if !body.is_object() {
+        return Err(anyhow::anyhow!("A form body must be an object"));
+    }
+    let mut fields = Vec::new();
+    flatten("", body, &mut fields)?;
+    Ok(fields)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::Gesture;
+    use crate::input::tests::test_identity;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("svbony-pipeline-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Step that runs a shell script in `dir` and waits for it
+    fn script_step(dir: &std::path::Path, script: &str) -> PipelineStep {
+        PipelineStep::Action {
+            name: None,
+            action_type: ActionType::RunCommand,
+            parameters: Box::new(ActionParameters {
+                command: Some("sh".to_string()),
+                args: Some(vec!["-c".to_string(), format!("cd '{}' && {}", dir.display(), script)]),
+                ..ActionParameters::default()
+            }),
+            wait: true,
+        }
+    }
+
+    fn action(action_type: ActionType, parameters: ActionParameters) -> ActionConfig {
+        ActionConfig {
+            name: "action".to_string(),
+            action_type,
+            parameters,
+            enabled: true,
+            button: ActionConfig::default_button(),
+            gesture: Gesture::Single,
+            device: None,
+            steps: Vec::new(),
+            on_failure: OnFailure::Stop,
+            persist: false,
+            reset_after_ms: None,
+        }
+    }
+
+    fn pipeline(steps: Vec<PipelineStep>, on_failure: OnFailure) -> ActionConfig {
+        ActionConfig {
+            name: "pipeline".to_string(),
+            steps,
+            on_failure,
+            ..action(ActionType::Pipeline, ActionParameters::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_runs_steps_in_order() {
+        let dir = scratch_dir("order");
+        let action = pipeline(vec![
+            script_step(&dir, "echo a >> log"),
+            PipelineStep::Delay { ms: 100 },
+            script_step(&dir, "echo b >> log"),
+        ], OnFailure::Stop);
+
+        let started = std::time::Instant::now();
+        ActionExecutor::new().execute_action(&action, &TemplateContext::default()).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "a\nb\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_step_follows_on_failure() {
+        let dir = scratch_dir("failure");
+        let steps = vec![
+            script_step(&dir, "echo a >> log"),
+            script_step(&dir, "exit 3"),
+            script_step(&dir, "echo b >> log"),
+        ];
+        let executor = ActionExecutor::new();
+
+        let error = executor.execute_action(&pipeline(steps.clone(), OnFailure::Stop), &TemplateContext::default()).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("Step 2 failed"));
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "a\n");
+
+        std::fs::remove_file(dir.join("log")).unwrap();
+        executor.execute_action(&pipeline(steps, OnFailure::Continue), &TemplateContext::default()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "a\nb\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parallel_steps_run_at_the_same_time() {
+        let dir = scratch_dir("parallel");
+        // The first step only succeeds if the second one runs while it waits
+        let wait_for_marker = "for i in $(seq 200); do [ -e marker ] && exit 0; sleep 0.01; done; exit 1";
+        let action = pipeline(vec![
+            PipelineStep::Parallel(vec![
+                script_step(&dir, wait_for_marker),
+                script_step(&dir, "touch marker"),
+            ]),
+            script_step(&dir, "echo done >> log"),
+        ], OnFailure::Stop);
+
+        ActionExecutor::new().execute_action(&action, &TemplateContext::default()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "done\n");
+
+        // One failing member fails the group
+        let action = pipeline(vec![
+            PipelineStep::Parallel(vec![
+                script_step(&dir, "exit 1"),
+                script_step(&dir, "true"),
+            ]),
+        ], OnFailure::Stop);
+        assert!(ActionExecutor::new().execute_action(&action, &TemplateContext::default()).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pipeline_from_json() {
+        let action: ActionConfig = serde_json::from_str(r#"{
+            "name": "Record meeting",
+            "action_type": "Pipeline",
+            "enabled": true,
+            "on_failure": "continue",
+            "steps": [
+                { "Action": { "action_type": "RunCommand", "parameters": { "command": "obs" } } },
+                { "Delay": { "ms": 2000 } },
+                { "Parallel": [
+                    { "Action": { "action_type": "OpenUrl", "parameters": { "url": "https://app.notta.ai" }, "wait": true } }
+                ] }
+            ]
+        }"#).unwrap();
+
+        assert_eq!(action.on_failure, OnFailure::Continue);
+        assert_eq!(action.steps.len(), 3);
+        assert!(matches!(action.steps[0], PipelineStep::Action { wait: false, .. }));
+        assert!(matches!(action.steps[1], PipelineStep::Delay { ms: 2000 }));
+        assert!(matches!(&action.steps[2], PipelineStep::Parallel(steps) if steps.len() == 1));
+        assert!(action.parameters.url.is_none());
+    }
+
+    /// Answer one HTTP request on a local port with `status` and `body`
+    /// after `delay`. Returns the URL to call and a channel that receives
+    /// the raw request.
+    fn serve_once(status: u16, delay: Duration, body: &str) -> (String, mpsc::Receiver<String>) {
+        let body = body.to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            // Read the head, then the body announced by Content-Length
+            loop {
+                let size = stream.read(&mut buffer).unwrap();
+                if size == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..size]);
+                if let Some(head_end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+                    let length: usize = head.lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .map(|length| length.trim().parse().unwrap())
+                        .unwrap_or(0);
+                    if request.len() >= head_end + 4 + length {
+                        break;
+                    }
+                }
+            }
+
+            std::thread::sleep(delay);
+            let _ = write!(stream, "HTTP/1.1 {} Test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, body.len(), body);
+            let _ = sender.send(String::from_utf8_lossy(&request).into_owned());
+        });
+
+        (url, receiver)
+    }
+
+    fn request_body(request: &str) -> &str {
+        request.split_once("\r\n\r\n").unwrap().1
+    }
+
+    #[tokio::test]
+    async fn test_webhook_posts_event_details() {
+        let (url, requests) = serve_once(200, Duration::ZERO, "");
+        let action = action(ActionType::Webhook, ActionParameters {
+            url: Some(url),
+            headers: Some(BTreeMap::from([("X-Token".to_string(), "secret-{{model}}".to_string())])),
+            ..ActionParameters::default()
+        });
+        let context = TemplateContext {
+            button: Some("AI".to_string()),
+            gesture: Some(Gesture::Double),
+            press_count: Some(2),
+            device: Some(test_identity("/dev/hidraw3", 0x364d, "M6", Some("A1B2"))),
+            ..TemplateContext::default()
+        };
+
+        ActionExecutor::new().execute_action(&action, &context).await.unwrap();
+
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1"));
+        assert!(request.to_lowercase().contains("x-token: secret-m6"));
+        assert!(request.to_lowercase().contains("content-type: application/json"));
+        let body: Value = serde_json::from_str(request_body(&request)).unwrap();
+        assert_eq!(body["button"], "AI");
+        assert_eq!(body["gesture"], "Double");
+        assert_eq!(body["press_count"], 2);
+        assert_eq!(body["device"]["serial_number"], "A1B2");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_checks_status() {
+        let (url, _requests) = serve_once(500, Duration::ZERO, "");
+        let failing = action(ActionType::Webhook, ActionParameters { url: Some(url), ..ActionParameters::default() });
+        let error = ActionExecutor::new().execute_action(&failing, &TemplateContext::default()).await.unwrap_err();
+        assert!(error.to_string().contains("500"));
+
+        let (url, requests) = serve_once(404, Duration::ZERO, "");
+        let action = action(ActionType::Webhook, ActionParameters {
+            url: Some(url),
+            method: Some(HttpMethod::Get),
+            success_status: Some(vec![200, 404]),
+            ..ActionParameters::default()
+        });
+        ActionExecutor::new().execute_action(&action, &TemplateContext::default()).await.unwrap();
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("GET /hook"));
+        assert_eq!(request_body(&request), "");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_form_body() {
+        let (url, requests) = serve_once(204, Duration::ZERO, "");
+        let action = action(ActionType::Webhook, ActionParameters {
+            url: Some(url),
+            method: Some(HttpMethod::Put),
+            body: Some(json!({ "text": "{{button}} pressed", "meeting": { "id": 7 } })),
+            body_format: Some(BodyFormat::Form),
+            ..ActionParameters::default()
+        });
+        let context = TemplateContext { button: Some("Call".to_string()), ..TemplateContext::default() };
+
+        ActionExecutor::new().execute_action(&action, &context).await.unwrap();
+
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("PUT /hook"));
+        // Fields are sorted by name
+        assert_eq!(request_body(&request), "meeting.id=7&text=Call+pressed");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_times_out() {
+        let (url, _requests) = serve_once(200, Duration::from_secs(2), "");
+        let action = action(ActionType::Webhook, ActionParameters {
+            url: Some(url),
+            timeout_ms: Some(100),
+            ..ActionParameters::default()
+        });
+
+        let started = std::time::Instant::now();
+        assert!(ActionExecutor::new().execute_action(&action, &TemplateContext::default()).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_sends_prompt_to_endpoint() {
+        let reply = r#"{ "choices": [{ "message": { "role": "assistant", "content": "Forty-two" } }] }"#;
+        let (url, requests) = serve_once(200, Duration::ZERO, reply);
+        let parameters = ActionParameters {
+            url: Some(url.trim_end_matches("/hook").to_string() + "/v1"),
+            model: Some("local-model".to_string()),
+            api_key: Some("sk-test".to_string()),
+            prompt: Some("What is the answer?".to_string()),
+            ..ActionParameters::default()
+        };
+
+        let answer = ActionExecutor::new().request_ai_reply(&parameters).await.unwrap();
+        assert_eq!(answer, "Forty-two");
+
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions"));
+        assert!(request.to_lowercase().contains("authorization: bearer sk-test"));
+        let body: Value = serde_json::from_str(request_body(&request)).unwrap();
+        assert_eq!(body["model"], "local-model");
+        assert_eq!(body["messages"][0]["content"], "What is the answer?");
+    }
+
+    #[tokio::test]
+    async fn test_ask_ai_reports_endpoint_errors() {
+        let (url, _requests) = serve_once(401, Duration::ZERO, r#"{ "error": { "message": "Invalid API key" } }"#);
+        let parameters = ActionParameters {
+            url: Some(url),
+            prompt: Some("Hello".to_string()),
+            ..ActionParameters::default()
+        };
+
+        let error = ActionExecutor::new().request_ai_reply(&parameters).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("Invalid API key"));
+    }
+
+    #[test]
+    fn test_notification_text_is_shortened() {
+        assert_eq!(notification_text("short"), "short");
+        let long = "word ".repeat(100);
+        let text = notification_text(&long);
+        assert_eq!(text.chars().count(), 300);
+        assert!(text.ends_with('…'));
+    }
+
+    fn toggle_action(parameters: ActionParameters) -> ActionConfig {
+        ActionConfig {
+            name: "Recording".to_string(),
+            ..action(ActionType::Toggle, parameters)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_toggle_starts_and_stops_process() {
+        let action = toggle_action(ActionParameters {
+            command: Some("sleep".to_string()),
+            args: Some(vec!["30".to_string()]),
+            ..ActionParameters::default()
+        });
+        let executor = ActionExecutor::new();
+        let context = TemplateContext::default();
+
+        assert!(executor.switch(&action, &context).await.unwrap());
+        assert!(executor.toggled.lock().await.contains_key("Recording"));
+
+        // A clone shares the running process, as the executor is cloned per
+        // press. The process exits on SIGTERM without being killed.
+        let started = std::time::Instant::now();
+        assert!(!executor.clone().switch(&action, &context).await.unwrap());
+        assert!(started.elapsed() < Duration::from_millis(DEFAULT_KILL_AFTER_MS));
+        assert!(executor.toggled.lock().await.is_empty());
+
+        // A process that exited by itself is started again
+        let action = toggle_action(ActionParameters {
+            command: Some("true".to_string()),
+            ..ActionParameters::default()
+        });
+        assert!(executor.switch(&action, &context).await.unwrap());
+        sleep_until_exited(&executor, "Recording").await;
+        assert!(executor.switch(&action, &context).await.unwrap());
+    }
+
+    async fn sleep_until_exited(executor: &ActionExecutor, name: &str) {
+        for _ in 0..200 {
+            if executor.toggled.lock().await.get_mut(name).unwrap().try_wait().unwrap().is_some() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{} did not exit", name);
+    }
+
+    #[tokio::test]
+    async fn test_toggle_alternates_start_and_stop_commands() {
+        let dir = scratch_dir("toggle");
+        let script = |text: &str| vec!["-c".to_string(), format!("echo {} >> '{}'", text, dir.join("log").display())];
+        let action = toggle_action(ActionParameters {
+            command: Some("sh".to_string()),
+            args: Some(script("start")),
+            stop_command: Some("sh".to_string()),
+            stop_args: Some(script("stop")),
+            ..ActionParameters::default()
+        });
+        let executor = ActionExecutor::new();
+        let context = TemplateContext::default();
+
+        assert!(executor.switch(&action, &context).await.unwrap());
+        assert!(executor.state.is_on("Recording"));
+        assert!(!executor.switch(&action, &context).await.unwrap());
+        assert!(executor.switch(&action, &context).await.unwrap());
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "start\nstop\nstart\n");
+
+        // A failing command leaves the state as it was
+        let failing = toggle_action(ActionParameters {
+            stop_args: Some(vec!["-c".to_string(), "exit 1".to_string()]),
+            ..action.parameters.clone()
+        });
+        assert!(executor.switch(&failing, &context).await.is_err());
+        assert!(executor.state.is_on("Recording"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cycle_runs_next_entry_on_every_press() {
+        let dir = scratch_dir("cycle");
+        let action = ActionConfig {
+            name: "AI tools".to_string(),
+            action_type: ActionType::Cycle,
+            ..pipeline(vec![
+                script_step(&dir, "echo a >> log"),
+                script_step(&dir, "echo b >> log"),
+                PipelineStep::Parallel(vec![script_step(&dir, "echo c >> log")]),
+            ], OnFailure::Stop)
+        };
+        let executor = ActionExecutor::new();
+
+        for _ in 0..4 {
+            executor.execute_action(&action, &TemplateContext::default()).await.unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "a\nb\nc\na\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_command_output_is_written_to_file() {
+        let dir = scratch_dir("output");
+        let output_file = dir.join("status.txt");
+        let parameters = ActionParameters {
+            command: Some("sh".to_string()),
+            args: Some(vec!["-c".to_string(), "echo recording saved to {{model}}.ogg".to_string()]),
+            output: Some(OutputTarget::File),
+            output_file: Some(output_file.display().to_string()),
+            ..ActionParameters::default()
+        };
+        let action = action(ActionType::RunCommand, parameters);
+        let context = TemplateContext {
+            device: Some(test_identity("/dev/hidraw3", 0x364d, "M6", None)),
+            ..TemplateContext::default()
+        };
+
+        ActionExecutor::new().execute_action(&action, &context).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "recording saved to M6.ogg\n");
+
+        // A failing command is an error that carries what it printed
+        let failing = ActionConfig {
+            parameters: ActionParameters {
+                args: Some(vec!["-c".to_string(), "echo no space left >&2; exit 1".to_string()]),
+                ..action.parameters.clone()
+            },
+            ..action.clone()
+        };
+        let error = ActionExecutor::new().execute_action(&failing, &context).await.unwrap_err();
+        assert!(error.to_string().contains("no space left"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_stopped_on_timeout_and_shutdown() {
+        let dir = scratch_dir("shutdown");
+        let executor = ActionExecutor::new();
+
+        let mut step = script_step(&dir, "sleep 30");
+        if let PipelineStep::Action { parameters, .. } = &mut step {
+            parameters.timeout_ms = Some(100);
+        }
+        let error = executor.execute_action(&pipeline(vec![step], OnFailure::Stop), &TemplateContext::default()).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("timed out after 100 ms"));
+
+        // Captured output would tie a detached command to the assistant
+        let mut step = script_step(&dir, "true");
+        if let PipelineStep::Action { parameters, .. } = &mut step {
+            parameters.detach = Some(true);
+            parameters.notify_on_error = Some(true);
+        }
+        let error = executor.execute_action(&pipeline(vec![step], OnFailure::Stop), &TemplateContext::default()).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("can't be detached"));
+
+        // A background command that starts a child of its own
+        let action = action(ActionType::RunCommand, ActionParameters {
+            command: Some("sh".to_string()),
+            args: Some(vec!["-c".to_string(), format!("sleep 30 & echo $! > '{}'; wait", dir.join("pid").display())]),
+            ..ActionParameters::default()
+        });
+        executor.execute_action(&action, &TemplateContext::default()).await.unwrap();
+        let pid = loop {
+            if let Ok(pid) = std::fs::read_to_string(dir.join("pid")) {
+                if let Ok(pid) = pid.trim().parse::<i32>() {
+                    break nix::unistd::Pid::from_raw(pid);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        let started = std::time::Instant::now();
+        executor.shutdown().await;
+        assert!(started.elapsed() < Duration::from_millis(DEFAULT_KILL_AFTER_MS));
+        // Give the exited sleep a moment to be reaped by init
+        for _ in 0..200 {
+            if nix::sys::signal::kill(pid, None).is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(nix::sys::signal::kill(pid, None).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plugin_protocol() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("plugin");
+        let plugin = |name: &str, body: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            action(ActionType::Plugin, ActionParameters {
+                plugin: Some(path.display().to_string()),
+                plugin_options: Some(json!({ "project": "{{button}}-board" })),
+                ..ActionParameters::default()
+            })
+        };
+        let context = TemplateContext {
+            button: Some("AI".to_string()),
+            ..TemplateContext::default()
+        };
+        let executor = ActionExecutor::new();
+
+        let request_file = dir.join("request.json");
+        let ok = plugin("ok", &format!(
+            "cat > '{}'; echo '{{\"protocol\": 1, \"status\": \"ok\", \"message\": \"done\"}}'",
+            request_file.display()
+        ));
+        executor.execute_action(&ok, &context).await.unwrap();
+        let request: Value = serde_json::from_str(&std::fs::read_to_string(&request_file).unwrap()).unwrap();
+        assert_eq!(request["protocol"], 1);
+        assert_eq!(request["event"]["button"], "AI");
+        assert_eq!(request["options"], json!({ "project": "AI-board" }));
+
+        let refused = plugin("refused", "echo '{\"protocol\": 1, \"status\": \"error\", \"message\": \"Not logged in\"}'");
+        let error = executor.execute_action(&refused, &context).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("Not logged in"));
+
+        let crashed = plugin("crashed", "echo 'No module named jira' >&2; exit 3");
+        let error = executor.execute_action(&crashed, &context).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("No module named jira"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_inline_script_gets_event_variables() {
+        let dir = scratch_dir("script");
+        let output_file = dir.join("out.txt");
+        let action = action(ActionType::RunScript, ActionParameters {
+            script: Some([
+                "echo \"$SVBONY_MODEL $SVBONY_GESTURE x$SVBONY_PRESS_COUNT\" | tr a-z A-Z",
+                "echo \"arg: $1\"",
+                "echo \"$0\"",
+            ].join("\n")),
+            args: Some(vec!["first".to_string()]),
+            output: Some(OutputTarget::File),
+            output_file: Some(output_file.display().to_string()),
+            ..ActionParameters::default()
+        });
+        let context = TemplateContext {
+            gesture: Some(Gesture::Double),
+            press_count: Some(2),
+            device: Some(test_identity("/dev/hidraw3", 0x364d, "m6", None)),
+            ..TemplateContext::default()
+        };
+
+        ActionExecutor::new().execute_action(&action, &context).await.unwrap();
+
+        let output = std::fs::read_to_string(&output_file).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[..2], ["M6 DOUBLE X2", "arg: first"]);
+        // The script file is gone once the script finished
+        assert!(lines[2].contains("svbony-script-"));
+        assert!(!std::path::Path::new(lines[2]).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn rhai_action(script: &str) -> ActionConfig {
+        action(ActionType::RhaiScript, ActionParameters {
+            script: Some(script.to_string()),
+            ..ActionParameters::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_rhai_script_uses_assistant_api() {
+        let (url, requests) = serve_once(200, Duration::ZERO, r#"{"count": 41}"#);
+        let context = TemplateContext {
+            button: Some("AI".to_string()),
+            press_count: Some(2),
+            ..TemplateContext::default()
+        };
+        let executor = ActionExecutor::new();
+
+        let script = format!(r#"
+            let presses = store_get("presses") ?? 0;
+            store_set("presses", presses + event.press_count);
+
+            let reply = http_post("{}", #{{ button: event.button }});
+            if reply.status != 200 {{ throw "bad status"; }}
+            store_set("count", reply.body.count + 1);
+
+            let echo = run_command("sh", ["-c", "echo $SVBONY_BUTTON $1", "sh", "hi"]);
+            let text = echo.stdout;
+            text.trim();
+            store_set("echo", text);
+            store_set("failed", run_command("false").status);
+        "#, url);
+        executor.execute_action(&rhai_action(&script), &context).await.unwrap();
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request_body(&request), r#"{"button":"AI"}"#);
+        assert_eq!(executor.state.stored("presses"), Some(json!(2)));
+        assert_eq!(executor.state.stored("count"), Some(json!(42)));
+        assert_eq!(executor.state.stored("echo"), Some(json!("AI hi")));
+        assert_eq!(executor.state.stored("failed"), Some(json!(1)));
+
+        // The store outlives the script
+        executor.execute_action(&rhai_action(&script.replace(&url, "http://127.0.0.1:1/")), &context).await.unwrap_err();
+        assert_eq!(executor.state.stored("presses"), Some(json!(4)));
+    }
+
+    #[tokio::test]
+    async fn test_rhai_script_errors() {
+        let executor = ActionExecutor::new();
+        let error = executor.execute_action(&rhai_action("let x = ;"), &TemplateContext::default()).await.unwrap_err();
+        assert!(error.to_string().starts_with("Script failed"), "{}", error);
+
+        let mut action = rhai_action("loop { }");
+        action.parameters.timeout_ms = Some(100);
+        action.parameters.max_operations = Some(u64::MAX);
+        let error = executor.execute_action(&action, &TemplateContext::default()).await.unwrap_err();
+        assert_eq!(error.to_string(), "Script ran longer than 100 ms");
+
+        // Commands only get the time the script has left
+        #[cfg(unix)]
+        {
+            let mut action = rhai_action(r#"run_command("sleep", ["30"])"#);
+            action.parameters.timeout_ms = Some(300);
+            let started = std::time::Instant::now();
+            let error = executor.execute_action(&action, &TemplateContext::default()).await.unwrap_err();
+            assert!(started.elapsed() < Duration::from_secs(10), "{:?}", started.elapsed());
+            assert!(error.to_string().contains("timed out"), "{}", error);
+        }
+
+        // Failures of the assistant's functions can be caught
+        let action = rhai_action(r#"try { run_command("/nonexistent/command") } catch (e) { store_set("error", e) }"#);
+        executor.execute_action(&action, &TemplateContext::default()).await.unwrap();
+        let error = executor.state.stored("error").unwrap();
+        assert!(error.as_str().unwrap().contains("Failed to run command /nonexistent/command"));
+    }
+
+    #[test]
+    fn test_interpreter_invocation() {
+        assert_eq!(script_suffix("python3"), ".py");
+        assert_eq!(script_suffix("C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe"), ".ps1");
+        assert_eq!(script_suffix("/bin/bash"), "");
+        assert_eq!(interpreter_args("pwsh").last(), Some(&"-File"));
+        assert!(interpreter_args("bash").is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+//! Requests to OpenAI-compatible chat completion endpoints for the AskAi
+//! action.
+//!
+//! Only the subset of the API that local servers (llama.cpp, Ollama,
+//! LM Studio, vLLM) implement as well is used: a system message with the
+//! prompt, a user message with the input text and the first choice of the
+//! reply.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+pub const DEFAULT_ENDPOINT: &str = "https://api.openai.com/v1";
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Chat completion URL for `endpoint`, which is either the full URL or the
+/// API base such as `http://localhost:11434/v1`
+pub fn completions_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/chat/completions") {
+        endpoint.to_string()
+    } else {
+        format!("{}/chat/completions", endpoint)
+    }
+}
+
+/// Request body that asks `model` to apply `prompt` to `input`. Without
+/// input the prompt is sent as the user's message.
+pub fn chat_request(model: &str, prompt: &str, input: Option<&str>) -> Value {
+    let messages = match input {
+        Some(input) => json!([
+            { "role": "system", "content": prompt },
+            { "role": "user", "content": input },
+        ]),
+        None => json!([
+            { "role": "user", "content": prompt },
+        ]),
+    };
+
+    json!({
+        "model": model,
+        "messages": messages,
+        "stream": false,
+    })
+}
+
+/// Text of the first choice in a chat completion response
+pub fn parse_reply(response: &Value) -> Result<String> {
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string());
+        return Err(anyhow::anyhow!("The AI endpoint returned an error: {}", message));
+    }
+
+    let content = response.pointer("/choices/0/message/content")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("The AI endpoint returned no reply"))?;
+    Ok(content.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completions_url() {
+        assert_eq!(completions_url("http://localhost:11434/v1/"), "http://localhost:11434/v1/chat/completions");
+        assert_eq!(completions_url("https://example.com/v1/chat/completions"), "https://example.com/v1/chat/completions");
+    }
+
+    #[test]
+    fn test_chat_request() {
+        let request = chat_request("llama3", "Summarize", Some("Long text"));
+        assert_eq!(request["model"], "llama3");
+        assert_eq!(request["messages"][0], json!({ "role": "system", "content": "Summarize" }));
+        assert_eq!(request["messages"][1], json!({ "role": "user", "content": "Long text" }));
+
+        let request = chat_request("llama3", "Tell a joke", None);
+        assert_eq!(request["messages"], json!([{ "role": "user", "content": "Tell a joke" }]));
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let response = json!({ "choices": [{ "index": 0, "message": { "role": "assistant", "content": " Short text\n" } }] });
+        assert_eq!(parse_reply(&response).unwrap(), "Short text");
+
+        let error = parse_reply(&json!({ "error": { "message": "Invalid API key" } })).unwrap_err();
+        assert!(error.to_string().contains("Invalid API key"));
+        assert!(parse_reply(&json!({ "choices": [] })).is_err());
+    }
+}
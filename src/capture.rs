@@ -0,0 +1,258 @@
+//! Recording raw HID reports to a file and playing them back.
+//!
+//! A capture is a text file with one report per line:
+//!
+//! ```text
+//! # offset_ms  vid:pid    model  serial  path          report
+//! 1532         e2b7:364d  M6     A1B2    /dev/hidraw3  04 b2 00 00 01 00 01 00
+//! ```
+//!
+//! Fields are separated by tabs, `-` stands for a missing serial number and
+//! lines starting with `#` are comments. Replaying a capture feeds the
+//! reports through the normal signature, gesture and action pipeline with
+//! their original timing.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config::parse_hex_bytes;
+use crate::hid_monitor::DeviceReport;
+use crate::input::{DeviceIdentity, ScriptStep, ScriptedConnector, ScriptedDevice, ScriptedSource};
+
+const CAPTURE_HEADER: &str = "# SVBONY AI Assistant HID capture\n# offset_ms\tvid:pid\tmodel\tserial\tpath\treport\n";
+
+/// One report read from a capture file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedReport {
+    /// Time since the capture started
+    pub offset: Duration,
+    pub device: DeviceIdentity,
+    /// Raw report, report ID first
+    pub data: Vec<u8>,
+}
+
+impl CapturedReport {
+    pub fn to_line(&self) -> String {
+        let data: Vec<String> = self.data.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}\t{:04x}:{:04x}\t{}\t{}\t{}\t{}",
+            self.offset.as_millis(),
+            self.device.vendor_id,
+            self.device.product_id,
+            self.device.model,
+            self.device.serial_number.as_deref().unwrap_or("-"),
+            self.device.path,
+            data.join(" "))
+    }
+
+    pub fn parse_line(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 6 {
+            return Err(anyhow::anyhow!("Expected 6 tab separated fields, found {}", fields.len()));
+        }
+
+        let offset_ms: u64 = fields[0].trim().parse()
+            .with_context(|| format!("Invalid offset '{}'", fields[0]))?;
+        let (vendor_id, product_id) = fields[1].split_once(':')
+            .and_then(|(vendor, product)| {
+                Some((u16::from_str_radix(vendor, 16).ok()?, u16::from_str_radix(product, 16).ok()?))
+            })
+            .with_context(|| format!("Invalid vid:pid '{}'", fields[1]))?;
+        let data = parse_hex_bytes(fields[5])?;
+        if data.is_empty() {
+            return Err(anyhow::anyhow!("Empty report"));
+        }
+
+        Ok(CapturedReport {
+            offset: Duration::from_millis(offset_ms),
+            device: DeviceIdentity {
+                path: fields[4].to_string(),
+                vendor_id,
+                product_id,
+                model: fields[2].to_string(),
+                serial_number: match fields[3] {
+                    "-" => None,
+                    serial => Some(serial.to_string()),
+                },
+            },
+            data,
+        })
+    }
+}
+
+/// Appends every report it is given to a capture file
+#[derive(Debug)]
+pub struct CaptureWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create capture file {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(CAPTURE_HEADER.as_bytes())?;
+        file.flush()?;
+
+        Ok(CaptureWriter {
+            file,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, device_report: &DeviceReport) -> Result<()> {
+        let report = &device_report.report;
+        let captured = CapturedReport {
+            offset: report.timestamp.saturating_duration_since(self.started),
+            device: device_report.device.clone(),
+            data: report.to_bytes(),
+        };
+
+        // Flush every line so the capture survives the process being killed
+        writeln!(self.file, "{}", captured.to_line())?;
+        self.file.flush()
+            .with_context(|| format!("Failed to write capture file {}", self.path.display()))
+    }
+}
+
+/// Parse the contents of a capture file. Reports are returned in time order.
+pub fn parse_capture(text: &str) -> Result<Vec<CapturedReport>> {
+    let mut reports = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let report = CapturedReport::parse_line(line)
+            .with_context(|| format!("Invalid capture line {}", index + 1))?;
+        reports.push(report);
+    }
+
+    reports.sort_by_key(|report| report.offset);
+    Ok(reports)
+}
+
+pub fn load_capture(path: &Path) -> Result<Vec<CapturedReport>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read capture file {}", path.display()))?;
+    parse_capture(&text)
+}
+
+/// Connector that plays `reports` back on one scripted device per captured
+/// device, keeping the time between reports
+pub fn replay_connector(reports: &[CapturedReport]) -> ScriptedConnector {
+    let mut devices: Vec<(DeviceIdentity, Vec<ScriptStep>, Duration)> = Vec::new();
+
+    for report in reports {
+        let index = match devices.iter().position(|(device, _, _)| device.path == report.device.path) {
+            Some(index) => index,
+            None => {
+                devices.push((report.device.clone(), Vec::new(), Duration::ZERO));
+                devices.len() - 1
+            }
+        };
+
+        let (_, steps, last_offset) = &mut devices[index];
+        if report.offset > *last_offset {
+            steps.push(ScriptStep::Wait(report.offset - *last_offset));
+        }
+        steps.push(ScriptStep::Report(report.data.clone()));
+        *last_offset = report.offset;
+    }
+
+    ScriptedConnector::new(devices.into_iter()
+        .map(|(device, steps, _)| {
+            let source = ScriptedSource::new(&format!("replay {}", device.path), steps);
+            ScriptedDevice::new(device, vec![source])
+        })
+        .collect())
+}
+
+/// Time from the start of the capture to its last report
+pub fn capture_length(reports: &[CapturedReport]) -> Duration {
+    reports.last().map(|report| report.offset).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::tests::test_identity;
+    use crate::input::DeviceConnector;
+
+    const CAPTURE: &str = "\
+# SVBONY AI Assistant HID capture
+0\te2b7:364d\tM6\tA1B2\t/dev/hidraw3\t04 b2 00 00 01 00 01 00
+250\te2b7:5053\tOmni2P\t-\t/dev/hidraw4\t05 01
+\r
+120\te2b7:364d\tM6\tA1B2\t/dev/hidraw3\t04 b2 00 00 00 00 00 00
+";
+
+    #[test]
+    fn test_parse_capture() {
+        let reports = parse_capture(CAPTURE).unwrap();
+        assert_eq!(reports.len(), 3);
+
+        // Sorted by offset
+        let offsets: Vec<u128> = reports.iter().map(|report| report.offset.as_millis()).collect();
+        assert_eq!(offsets, vec![0, 120, 250]);
+
+        assert_eq!(reports[0].device, test_identity("/dev/hidraw3", 0x364d, "M6", Some("A1B2")));
+        assert_eq!(reports[0].data, vec![0x04, 0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00]);
+        assert_eq!(reports[2].device.serial_number, None);
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        let report = CapturedReport {
+            offset: Duration::from_millis(1532),
+            device: test_identity("/dev/hidraw3", 0x364d, "M6", None),
+            data: vec![0x04, 0xb2],
+        };
+        let line = report.to_line();
+        assert_eq!(line, "1532\te2b7:364d\tM6\t-\t/dev/hidraw3\t04 b2");
+        assert_eq!(CapturedReport::parse_line(&line).unwrap(), report);
+    }
+
+    #[test]
+    fn test_invalid_lines_report_line_number() {
+        let error = parse_capture("# header\n0\te2b7:364d\tM6\t-\t/dev/hidraw3\n").unwrap_err();
+        assert!(format!("{:#}", error).contains("line 2"));
+
+        assert!(CapturedReport::parse_line("x\te2b7:364d\tM6\t-\t/dev/hidraw3\t04").is_err());
+        assert!(CapturedReport::parse_line("0\te2b7\tM6\t-\t/dev/hidraw3\t04").is_err());
+        assert!(CapturedReport::parse_line("0\te2b7:364d\tM6\t-\t/dev/hidraw3\t").is_err());
+    }
+
+    #[test]
+    fn test_replay_connector_keeps_devices_apart() {
+        let reports = parse_capture(CAPTURE).unwrap();
+        assert_eq!(capture_length(&reports), Duration::from_millis(250));
+
+        let mut connector = replay_connector(&reports);
+        let devices = connector.scan().unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].model, "M6");
+
+        let mut source = connector.open(&devices[0]).unwrap();
+        let mut buffer = [0u8; 64];
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let size = source.read_report(&mut buffer, 50).unwrap();
+            if size > 0 {
+                received.push(buffer[..size].to_vec());
+            }
+        }
+        assert_eq!(received[1], vec![0x04, 0xb2, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    }
+}
@@ -1,8 +1,9 @@
 use clap::{Arg, ArgAction, Command};
 use anyhow::{Context, Result};
 use log::{info};
+use std::path::PathBuf;
 
-use crate::config::Config;
+use crate::config::{Config, DeviceFilter};
 use crate::autostart::AutostartManager;
 use crate::background::BackgroundService;
 
@@ -19,6 +20,9 @@ pub struct CliArgs {
     pub version: bool,
     pub verbose: bool,
     pub quiet: bool,
+    pub capture: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub learn: Option<String>,
 }
 
 impl CliArgs {
@@ -91,6 +95,32 @@ impl CliArgs {
                     .long_help("Run in quiet mode, suppressing all log output except for error messages.")
                     .action(ArgAction::SetTrue)
             )
+            .arg(
+                Arg::new("capture")
+                    .long("capture")
+                    .value_name("FILE")
+                    .help("Record every HID report to FILE")
+                    .long_help("Record every raw HID report, with its time and device, to FILE while running normally in the foreground. Attach the file to bug reports about buttons that are not recognized.")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("replay")
+                    .long("replay")
+                    .value_name("FILE")
+                    .help("Replay HID reports recorded with --capture")
+                    .long_help("Play back the reports recorded in FILE with their original timing instead of reading real devices. Configured actions are executed as if the buttons had been pressed. Exits when the replay is finished.")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("learn")
+                    .long("learn")
+                    .value_name("NAME")
+                    .help("Learn the signature of button NAME from live presses")
+                    .long_help("Record the traffic of the attached device, ask you to press and release the button several times and save the derived report signature as button NAME in the configuration file, then exit.")
+                    .action(ArgAction::Set)
+            )
             .arg(
                 Arg::new("version")
                     .short('V')
@@ -111,6 +141,9 @@ impl CliArgs {
         args.version = matches.get_flag("version");
         args.verbose = matches.get_flag("verbose");
         args.quiet = matches.get_flag("quiet");
+        args.capture = matches.get_one::<PathBuf>("capture").cloned();
+        args.replay = matches.get_one::<PathBuf>("replay").cloned();
+        args.learn = matches.get_one::<String>("learn").cloned();
 
         // Validate conflicting arguments
         if args.foreground && args.background {
@@ -125,12 +158,24 @@ impl CliArgs {
             return Err(anyhow::anyhow!("Cannot specify both --enable-autostart and --disable-autostart"));
         }
 
+        if args.capture.is_some() && args.replay.is_some() {
+            return Err(anyhow::anyhow!("Cannot specify both --capture and --replay"));
+        }
+
+        if args.background && (args.capture.is_some() || args.replay.is_some() || args.learn.is_some()) {
+            return Err(anyhow::anyhow!("--capture, --replay and --learn run in the foreground"));
+        }
+
+        if args.learn.is_some() && (args.capture.is_some() || args.replay.is_some()) {
+            return Err(anyhow::anyhow!("Cannot combine --learn with --capture or --replay"));
+        }
+
         Ok(args)
     }
 
     /// Determine if we should run in background mode
     pub fn should_run_in_background(&self) -> bool {
-        if self.foreground {
+        if self.foreground || self.capture.is_some() || self.replay.is_some() || self.learn.is_some() {
             false
         } else {
             // Default to background mode unless explicitly running in foreground
@@ -290,6 +335,35 @@ impl CliArgs {
         println!("Minimize to tray: {}", config.settings.minimize_to_tray);
         println!("Log level: {}", config.settings.log_level);
         println!("Check updates: {}", config.settings.check_updates);
+        println!("Multi-press window: {} ms", config.settings.gestures.multi_press_window_ms);
+        println!("Long press: {} ms", config.settings.gestures.long_press_ms);
+        if let Some(mqtt) = &config.settings.mqtt {
+            let port = mqtt.port.map(|port| format!(":{}", port)).unwrap_or_default();
+            println!("MQTT broker: {}{}{}", mqtt.host, port, if mqtt.tls.is_some() { " (TLS)" } else { "" });
+            for trigger in &mqtt.triggers {
+                println!("   {} presses {} ({:?})", trigger.topic, trigger.button, trigger.gesture);
+            }
+        }
+        println!();
+
+        println!("Button Signatures ({} total)", config.buttons.len());
+        println!("------------------");
+        for signature in &config.buttons {
+            if let Some(usage) = &signature.usage {
+                println!("{}: usage {}", signature.name, usage);
+            } else {
+                let bytes: Vec<String> = signature.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                println!("{}: report {:02x}, offset {}, bytes {}",
+                    signature.name, signature.report_id, signature.offset, bytes.join(" "));
+            }
+            if !signature.mask.is_empty() {
+                let mask: Vec<String> = signature.mask.iter().map(|b| format!("{:02x}", b)).collect();
+                println!("   Mask: {}", mask.join(" "));
+            }
+            if let Some(device) = &signature.device {
+                println!("   Device: {}", device_scope(device));
+            }
+        }
         println!();
 
         println!("Configured Actions ({} total)", config.actions.len());
@@ -301,6 +375,11 @@ impl CliArgs {
                 if action.enabled { "Enabled" } else { "Disabled" }
             );
             println!("   Type: {:?}", action.action_type);
+            println!("   Button: {}", action.button);
+            println!("   Gesture: {:?}", action.gesture);
+            if let Some(device) = &action.device {
+                println!("   Device: {}", device_scope(device));
+            }
             
             match action.action_type {
                 crate::config::ActionType::OpenUrl => {
@@ -328,10 +407,77 @@ impl CliArgs {
                     if let Some(message) = &action.parameters.message {
                         println!("   Message: {}", message);
                     }
+                    for button in action.parameters.buttons.iter().flatten() {
+                        println!("   Button: {} -> {}", button.label, button.action);
+                    }
+                }
+                crate::config::ActionType::Webhook => {
+                    if let Some(url) = &action.parameters.url {
+                        println!("   URL: {} {}", action.parameters.method.unwrap_or_default().as_str(), url);
+                    }
+                }
+                crate::config::ActionType::AskAi => {
+                    if let Some(prompt) = &action.parameters.prompt {
+                        println!("   Prompt: {}", prompt);
+                    }
+                    println!("   Model: {}", action.parameters.model.as_deref().unwrap_or(crate::assistant::DEFAULT_MODEL));
+                }
+                crate::config::ActionType::Plugin => {
+                    if let Some(plugin) = &action.parameters.plugin {
+                        println!("   Plugin: {}", plugin);
+                    }
+                }
+                crate::config::ActionType::RunScript => {
+                    println!("   Interpreter: {}", action.parameters.interpreter.as_deref().unwrap_or("default"));
+                    if let Some(script) = &action.parameters.script {
+                        println!("   Script: {} lines", script.lines().count());
+                    }
+                }
+                crate::config::ActionType::MqttPublish => {
+                    if let Some(topic) = &action.parameters.topic {
+                        println!("   Topic: {}", topic);
+                    }
+                }
+                crate::config::ActionType::RhaiScript => {
+                    if let Some(script) = &action.parameters.script {
+                        println!("   Rhai script: {} lines", script.lines().count());
+                    }
+                }
+                crate::config::ActionType::Toggle => {
+                    if let Some(command) = &action.parameters.command {
+                        println!("   Command: {}", command);
+                        if let Some(args) = &action.parameters.args {
+                            println!("   Arguments: {:?}", args);
+                        }
+                    }
+                    if let Some(stop_command) = &action.parameters.stop_command {
+                        println!("   Stop Command: {}", stop_command);
+                        if let Some(stop_args) = &action.parameters.stop_args {
+                            println!("   Stop Arguments: {:?}", stop_args);
+                        }
+                    }
+                }
+                crate::config::ActionType::Pipeline => {
+                    println!("   Steps: {} (on failure: {:?})", action.steps.len(), action.on_failure);
+                }
+                crate::config::ActionType::Cycle => {
+                    println!("   Entries: {}{}", action.steps.len(), if action.persist { " (position saved)" } else { "" });
+                    if let Some(reset_after_ms) = action.reset_after_ms {
+                        println!("   Starts over after: {} ms without presses", reset_after_ms);
+                    }
                 }
             }
             println!();
         }
+
+        if let Ok(dir) = crate::plugin::plugins_dir() {
+            let plugins = crate::plugin::discover(&dir);
+            println!("Plugins ({} in {})", plugins.len(), dir.display());
+            println!("-------");
+            for (name, path) in &plugins {
+                println!("{}: {}", name, path.display());
+            }
+        }
     }
 
     /// Configure autostart
@@ -402,6 +548,21 @@ impl CliArgs {
     }
 }
 
+/// Describe the devices a filter selects, e.g. "PID 364d, model M6"
+fn device_scope(device: &DeviceFilter) -> String {
+    let mut scope = Vec::new();
+    if let Some(product_id) = device.product_id {
+        scope.push(format!("PID {:04x}", product_id));
+    }
+    if let Some(model) = &device.model {
+        scope.push(format!("model {}", model));
+    }
+    if let Some(serial) = &device.serial_number {
+        scope.push(format!("serial {}", serial));
+    }
+    scope.join(", ")
+}
+
 /// Helper function to check if another instance is running
 pub fn check_single_instance() -> Result<bool> {
     // This will be implemented using the BackgroundService functionality
@@ -430,6 +591,13 @@ pub fn show_usage_examples() {
     println!("Regenerate default configuration files:");
     println!("  svbony-ai-assistant --regenerate-config");
     println!();
+    println!("Record HID reports for a bug report, then replay them:");
+    println!("  svbony-ai-assistant --capture buttons.txt");
+    println!("  svbony-ai-assistant --replay buttons.txt");
+    println!();
+    println!("Learn a new button and save it to the configuration:");
+    println!("  svbony-ai-assistant --learn Mute");
+    println!();
     println!("Run quietly (errors only):");
     println!("  svbony-ai-assistant --quiet");
     println!();
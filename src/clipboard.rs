@@ -0,0 +1,126 @@
+//! Reading and writing clipboard text through the platform's clipboard
+//! tools: `wl-paste`/`wl-copy` on Wayland, `xclip` or `xsel` on X11,
+//! `pbpaste`/`pbcopy` on macOS and PowerShell on Windows.
+
+use anyhow::{Context, Result};
+use log::debug;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::TextSource;
+
+/// Commands to try in order to read `source`, as (program, arguments)
+fn read_commands(source: TextSource) -> Vec<(&'static str, Vec<&'static str>)> {
+    #[cfg(target_os = "linux")]
+    {
+        let primary = source == TextSource::Selection;
+        let wayland = ("wl-paste", if primary { vec!["--no-newline", "--primary"] } else { vec!["--no-newline"] });
+        let xclip = ("xclip", vec!["-o", "-selection", if primary { "primary" } else { "clipboard" }]);
+        let xsel = ("xsel", vec!["--output", if primary { "--primary" } else { "--clipboard" }]);
+
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            vec![wayland, xclip, xsel]
+        } else {
+            vec![xclip, xsel, wayland]
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let _ = source;
+        vec![("pbpaste", vec![])]
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let _ = source;
+        vec![("powershell", vec!["-NoProfile", "-NonInteractive", "-Command", "Get-Clipboard -Raw"])]
+    }
+}
+
+fn write_commands() -> Vec<(&'static str, Vec<&'static str>)> {
+    #[cfg(target_os = "linux")]
+    {
+        let wayland = ("wl-copy", vec![]);
+        let xclip = ("xclip", vec!["-i", "-selection", "clipboard"]);
+        let xsel = ("xsel", vec!["--input", "--clipboard"]);
+
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            vec![wayland, xclip, xsel]
+        } else {
+            vec![xclip, xsel, wayland]
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        vec![("pbcopy", vec![])]
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        vec![("powershell", vec!["-NoProfile", "-NonInteractive", "-Command", "$input | Set-Clipboard"])]
+    }
+}
+
+/// Read the text in `source`
+pub async fn read_text(source: TextSource) -> Result<String> {
+    let mut errors = Vec::new();
+
+    for (program, args) in read_commands(source) {
+        let output = match Command::new(program).args(&args).stdin(Stdio::null()).output().await {
+            Ok(output) => output,
+            Err(e) => {
+                errors.push(format!("{}: {}", program, e));
+                continue;
+            }
+        };
+
+        if output.status.success() {
+            debug!("Read {} bytes from the {:?} with {}", output.stdout.len(), source, program);
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+
+        // An empty clipboard is reported as an error by some tools
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        errors.push(format!("{}: {}", program, stderr.trim()));
+    }
+
+    Err(anyhow::anyhow!("Failed to read the {:?}: {}", source, errors.join("; ")))
+}
+
+/// Put `text` on the clipboard
+pub async fn write_text(text: &str) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for (program, args) in write_commands() {
+        let mut child = match Command::new(program)
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                errors.push(format!("{}: {}", program, e));
+                continue;
+            }
+        };
+
+        let mut stdin = child.stdin.take().context("Clipboard tool has no input")?;
+        stdin.write_all(text.as_bytes()).await?;
+        // Closing the input tells the tool the text is complete
+        drop(stdin);
+
+        let status = child.wait().await?;
+        if status.success() {
+            debug!("Copied {} bytes to the clipboard with {}", text.len(), program);
+            return Ok(());
+        }
+        errors.push(format!("{}: exited with {}", program, status));
+    }
+
+    Err(anyhow::anyhow!("Failed to write the clipboard: {}", errors.join("; ")))
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use log::{info, debug, warn};
@@ -7,6 +8,8 @@ use log::{info, debug, warn};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub actions: Vec<ActionConfig>,
+    #[serde(default = "Config::default_buttons")]
+    pub buttons: Vec<ButtonSignature>,
     pub settings: AppSettings,
     pub version: String,
 }
@@ -15,8 +18,106 @@ pub struct Config {
 pub struct ActionConfig {
     pub name: String,
     pub action_type: ActionType,
+    #[serde(default)]
     pub parameters: ActionParameters,
     pub enabled: bool,
+    /// Button that triggers the action: the name of a configured signature
+    /// or of a usage decoded from the report descriptor, such as "Mute"
+    #[serde(default = "ActionConfig::default_button")]
+    pub button: String,
+    #[serde(default)]
+    pub gesture: Gesture,
+    /// Only run for presses on matching devices; all devices when unset
+    #[serde(default)]
+    pub device: Option<DeviceFilter>,
+    /// Steps of a Pipeline action, or the entries of a Cycle action
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<PipelineStep>,
+    /// What a Pipeline action does when one of its steps fails
+    #[serde(default)]
+    pub on_failure: OnFailure,
+    /// Keep the position of a Cycle action across restarts
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub persist: bool,
+    /// Start a Cycle action over when it was not used for this long
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reset_after_ms: Option<u64>,
+}
+
+impl ActionConfig {
+    /// Actions written before buttons could be chosen are bound to the AI button
+    pub fn default_button() -> String {
+        "AI".to_string()
+    }
+}
+
+/// Selects devices by product ID, model name and/or serial number.
+/// Every field that is set must match.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceFilter {
+    #[serde(default, with = "hex_u16_opt")]
+    pub product_id: Option<u16>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+}
+
+impl DeviceFilter {
+    pub fn matches(&self, product_id: u16, model: &str, serial_number: Option<&str>) -> bool {
+        if self.product_id.is_some_and(|expected| expected != product_id) {
+            return false;
+        }
+
+        if let Some(expected) = &self.model {
+            if !expected.eq_ignore_ascii_case(model) {
+                return false;
+            }
+        }
+
+        if let Some(expected) = &self.serial_number {
+            if serial_number != Some(expected.as_str()) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Button gesture an action is bound to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Gesture {
+    #[default]
+    Single,
+    Double,
+    Triple,
+    LongPress,
+    /// Fired as soon as the button goes down, before any other gesture
+    Press,
+    /// Fired when the button comes back up
+    Release,
+}
+
+impl Gesture {
+    /// Number of presses that make up this gesture, for multi-press gestures
+    pub fn press_count(&self) -> Option<u32> {
+        match self {
+            Gesture::Single => Some(1),
+            Gesture::Double => Some(2),
+            Gesture::Triple => Some(3),
+            _ => None,
+        }
+    }
+
+    pub fn from_press_count(count: u32) -> Option<Self> {
+        match count {
+            1 => Some(Gesture::Single),
+            2 => Some(Gesture::Double),
+            3 => Some(Gesture::Triple),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,9 +126,60 @@ pub enum ActionType {
     RunCommand,
     SendKeys,
     ShowNotification,
+    /// Runs the inline `script` with `interpreter`
+    RunScript,
+    /// Runs `script` in the embedded Rhai engine (see `scripting.rs`)
+    RhaiScript,
+    /// Sends an HTTP request
+    Webhook,
+    /// Sends a prompt and the clipboard text to an OpenAI-compatible chat
+    /// endpoint and shows the reply
+    AskAi,
+    /// Runs the external `plugin` (see `plugin.rs` for the protocol)
+    Plugin,
+    /// Publishes `payload` on the MQTT `topic`
+    MqttPublish,
+    /// Runs the action's `steps`
+    Pipeline,
+    /// Alternates between starting and stopping `command`, or between
+    /// `command` and `stop_command` when that is set
+    Toggle,
+    /// Runs the next of its `steps` on every press
+    Cycle,
 }
 
+/// One step of a Pipeline action
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PipelineStep {
+    /// Run an action. A command normally runs in the background; with
+    /// `wait` the step ends when it exits and fails on a non-zero status.
+    Action {
+        #[serde(default)]
+        name: Option<String>,
+        action_type: ActionType,
+        #[serde(default)]
+        parameters: Box<ActionParameters>,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// Run the steps at the same time; the group ends when all of them did
+    /// and fails when any of them failed
+    Parallel(Vec<PipelineStep>),
+    /// Pause before the next step
+    Delay { ms: u64 },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFailure {
+    /// Skip the remaining steps
+    #[default]
+    Stop,
+    /// Log the failure and run the remaining steps
+    Continue,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ActionParameters {
     pub url: Option<String>,
     pub command: Option<String>,
@@ -35,6 +187,307 @@ pub struct ActionParameters {
     pub keys: Option<String>,
     pub message: Option<String>,
     pub title: Option<String>,
+    /// HTTP method of a Webhook action (default POST)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<HttpMethod>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Webhook body; the details of the button event when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_format: Option<BodyFormat>,
+    /// Milliseconds before a webhook or AI request gives up or a command is
+    /// stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Status codes a Webhook action accepts; any 2xx status when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_status: Option<Vec<u16>>,
+    /// Instructions sent to the model by an AskAi action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Text an AskAi action sends along with the prompt; only the prompt
+    /// when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<TextSource>,
+    /// Copy the reply of an AskAi action to the clipboard
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copy_reply: Option<bool>,
+    /// Where a RunCommand action puts what the command printed; the command
+    /// is waited for when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_file: Option<String>,
+    /// Bytes of output kept per stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<usize>,
+    /// Show a notification when a RunCommand action's command fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify_on_error: Option<bool>,
+    /// Milliseconds a command gets to exit after SIGTERM before it is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kill_after_ms: Option<u64>,
+    /// Leave the command running when the assistant exits; not together with
+    /// `output` or `notify_on_error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detach: Option<bool>,
+    /// Icon of a notification: a name from the icon theme or an image path
+    /// (Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urgency: Option<Urgency>,
+    /// Milliseconds until a notification disappears; 0 keeps it until it is
+    /// dismissed (Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_ms: Option<u32>,
+    /// A notification replaces the last one shown with the same tag (Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Buttons of a notification, each running an action (Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buttons: Option<Vec<NotificationButton>>,
+    /// Name of the plugin in the plugins directory, or path of its
+    /// executable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+    /// Passed to the plugin as they are, after template expansion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin_options: Option<serde_json::Value>,
+    /// Script run by a RunScript or RhaiScript action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Operations a RhaiScript action may run before it is stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_operations: Option<u64>,
+    /// Program that runs the script, such as "bash" or "python3"; "sh" or
+    /// "powershell" on Windows when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    /// MQTT topic an MqttPublish action publishes on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// Message an MqttPublish action publishes; the details of the button
+    /// event when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    /// MQTT quality of service: 0 (default), 1 or 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qos: Option<u8>,
+    /// The broker keeps the message for clients that subscribe later
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retain: Option<bool>,
+    /// Command that switches a Toggle action off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    Get,
+    #[default]
+    Post,
+    Put,
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+        }
+    }
+}
+
+/// Where the output of a command goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputTarget {
+    Notification,
+    /// The file named by `output_file`, replacing its contents
+    File,
+    Clipboard,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    /// Stays until it is dismissed on most desktops
+    Critical,
+}
+
+/// A notification button that runs the action named `action` when clicked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationButton {
+    pub label: String,
+    pub action: String,
+}
+
+/// Where an action takes its input text from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextSource {
+    #[default]
+    Clipboard,
+    /// The primary selection on Linux (the currently selected text); the
+    /// clipboard on other systems
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    /// `application/x-www-form-urlencoded`; the body must be a flat object
+    Form,
+}
+
+/// Byte signature that identifies a button press in a raw HID report.
+///
+/// `bytes` are compared against the report payload (the data after the report
+/// id) starting at `offset`. Only bits set in `mask` are compared; an empty or
+/// short mask compares the remaining bytes in full.
+///
+/// `release_bytes` describe the report sent when the button comes back up,
+/// compared the same way. Without them every press is treated as an
+/// immediate press and release, so long presses cannot be detected.
+///
+/// A signature with a `usage` matches no bytes at all: it gives the control
+/// of that name decoded from the report descriptor the signature's name.
+/// `device` limits a signature to some devices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonSignature {
+    pub name: String,
+    #[serde(default)]
+    pub report_id: u8,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default, with = "hex_bytes")]
+    pub bytes: Vec<u8>,
+    #[serde(default, with = "hex_bytes")]
+    pub mask: Vec<u8>,
+    #[serde(default, with = "hex_bytes")]
+    pub release_bytes: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceFilter>,
+}
+
+impl ButtonSignature {
+    /// Check a report (report id plus payload) against this signature
+    pub fn matches(&self, report_id: u8, payload: &[u8]) -> bool {
+        self.matches_bytes(report_id, payload, &self.bytes)
+    }
+
+    /// Check whether a report is the release report of this button
+    pub fn matches_release(&self, report_id: u8, payload: &[u8]) -> bool {
+        !self.release_bytes.is_empty() && self.matches_bytes(report_id, payload, &self.release_bytes)
+    }
+
+    pub fn has_release(&self) -> bool {
+        !self.release_bytes.is_empty()
+    }
+
+    /// Whether the signature is used for a device; all devices without a filter
+    pub fn applies_to(&self, product_id: u16, model: &str, serial_number: Option<&str>) -> bool {
+        match &self.device {
+            Some(filter) => filter.matches(product_id, model, serial_number),
+            None => true,
+        }
+    }
+
+    fn matches_bytes(&self, report_id: u8, payload: &[u8], bytes: &[u8]) -> bool {
+        if self.usage.is_some() || report_id != self.report_id {
+            return false;
+        }
+
+        let end = self.offset + bytes.len();
+        if payload.len() < end {
+            return false;
+        }
+
+        bytes.iter()
+            .zip(&payload[self.offset..end])
+            .enumerate()
+            .all(|(i, (expected, actual))| {
+                let mask = self.mask.get(i).copied().unwrap_or(0xff);
+                expected & mask == actual & mask
+            })
+    }
+}
+
+/// Serialize byte vectors as space separated hex strings ("b2 00 01"),
+/// which is much easier to edit by hand than a JSON number array
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex = bytes.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::parse_hex_bytes(&hex).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serialize optional USB IDs as hex strings ("364d"); "0x" prefixes are accepted
+mod hex_u16_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<u16>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.serialize_str(&format!("{:04x}", value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u16>, D::Error> {
+        let hex = match Option::<String>::deserialize(deserializer)? {
+            Some(hex) => hex,
+            None => return Ok(None),
+        };
+
+        let digits = hex.trim().trim_start_matches("0x").trim_start_matches("0X");
+        u16::from_str_radix(digits, 16)
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("Invalid hex ID '{}': {}", hex, e)))
+    }
+}
+
+/// Parse hex bytes written as "b2 00 01", "b2:00:01" or "b20001"
+pub fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>> {
+    let digits: String = hex.chars()
+        .filter(|c| !c.is_whitespace() && *c != ':' && *c != ',')
+        .collect();
+
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!("Invalid hex digits in '{}'", hex));
+    }
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(anyhow::anyhow!("Odd number of hex digits in '{}'", hex));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).context("Invalid hex byte"))
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +496,78 @@ pub struct AppSettings {
     pub minimize_to_tray: bool,
     pub log_level: String,
     pub check_updates: bool,
+    #[serde(default)]
+    pub gestures: GestureSettings,
+    /// Broker for MqttPublish actions and MQTT triggers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<MqttSettings>,
+}
+
+/// Timing thresholds for gesture detection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GestureSettings {
+    /// Maximum gap between a release and the next press of a double/triple press
+    pub multi_press_window_ms: u64,
+    /// How long the button must be held for a long press
+    pub long_press_ms: u64,
+}
+
+/// Connection to an MQTT broker (see `mqtt.rs`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttSettings {
+    pub host: String,
+    /// 1883, or 8883 with TLS, when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Name of this client at the broker; "svbony-ai-assistant-" and the
+    /// host name when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Connect with TLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<MqttTls>,
+    /// Topics whose messages press a button
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<MqttTrigger>,
+}
+
+/// TLS settings of an MQTT connection. The broker's certificate is checked
+/// against the system's certificates unless `ca_file` is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MqttTls {
+    /// PEM file with the certificate authority of the broker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    /// PEM files with the certificate and key of this client, for brokers
+    /// that ask for one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key_file: Option<String>,
+}
+
+/// A message on `topic` runs the actions bound to `button` and `gesture`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttTrigger {
+    /// Topic filter, which may contain the wildcards `+` and `#`
+    pub topic: String,
+    #[serde(default = "ActionConfig::default_button")]
+    pub button: String,
+    #[serde(default)]
+    pub gesture: Gesture,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        GestureSettings {
+            multi_press_window_ms: 400,
+            long_press_ms: 800,
+        }
+    }
 }
 
 impl Default for Config {
@@ -54,34 +579,50 @@ impl Default for Config {
                     action_type: ActionType::OpenUrl,
                     parameters: ActionParameters {
                         url: Some("https://app.notta.ai".to_string()),
-                        command: None,
-                        args: None,
-                        keys: None,
-                        message: None,
-                        title: None,
+                        ..ActionParameters::default()
                     },
                     enabled: true,
+                    button: ActionConfig::default_button(),
+                    gesture: Gesture::Single,
+                    device: None,
+                    steps: Vec::new(),
+                    on_failure: OnFailure::Stop,
+                    persist: false,
+                    reset_after_ms: None,
                 },
                 ActionConfig {
                     name: "Show AI Assistant Notification".to_string(),
                     action_type: ActionType::ShowNotification,
                     parameters: ActionParameters {
-                        url: None,
-                        command: None,
-                        args: None,
-                        keys: None,
                         message: Some("AI Assistant activated!".to_string()),
                         title: Some("SVBONY AI Assistant".to_string()),
+                        ..ActionParameters::default()
                     },
                     enabled: true,
+                    button: ActionConfig::default_button(),
+                    gesture: Gesture::Single,
+                    device: None,
+                    steps: Vec::new(),
+                    on_failure: OnFailure::Stop,
+                    persist: false,
+                    reset_after_ms: None,
                 },
             ],
+            buttons: Self::default_buttons(),
             settings: AppSettings::default(),
             version: env!("CARGO_PKG_VERSION").to_string(),
         }
     }
 }
 
+/// Button names given to the descriptor usages of the M6 and the Omni2P
+const DEFAULT_PRODUCT_BUTTONS: [(&str, &str); 4] = [
+    ("Mute", "Phone Mute"),
+    ("Volume Up", "Volume Increment"),
+    ("Volume Down", "Volume Decrement"),
+    ("Call", "Hook Switch"),
+];
+
 impl Default for AppSettings {
     fn default() -> Self {
         AppSettings {
@@ -89,11 +630,46 @@ impl Default for AppSettings {
             minimize_to_tray: true,
             log_level: "info".to_string(),
             check_updates: true,
+            gestures: GestureSettings::default(),
+            mqtt: None,
         }
     }
 }
 
 impl Config {
+    /// Signatures shipped with the application: the AI button report
+    /// `04 b2 00 00 01 00 01 00 ...` sent by both the M6 and the Omni2P, and
+    /// for each of them the telephony and volume controls their report
+    /// descriptors declare, under shorter names
+    pub fn default_buttons() -> Vec<ButtonSignature> {
+        let mut buttons = vec![ButtonSignature {
+            name: "AI".to_string(),
+            report_id: 0x04,
+            offset: 0,
+            bytes: vec![0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00],
+            mask: Vec::new(),
+            release_bytes: Vec::new(),
+            usage: None,
+            device: None,
+        }];
+
+        for product_id in [crate::M6_PRODUCT_ID, crate::OMNI2P_PRODUCT_ID] {
+            for (name, usage) in DEFAULT_PRODUCT_BUTTONS {
+                buttons.push(ButtonSignature {
+                    name: name.to_string(),
+                    report_id: 0,
+                    offset: 0,
+                    bytes: Vec::new(),
+                    mask: Vec::new(),
+                    release_bytes: Vec::new(),
+                    usage: Some(usage.to_string()),
+                    device: Some(DeviceFilter { product_id: Some(product_id), ..DeviceFilter::default() }),
+                });
+            }
+        }
+        buttons
+    }
+
     pub fn get_config_path() -> Result<PathBuf> {
         let config_dir = dirs::config_dir()
             .context("Failed to get user config directory")?
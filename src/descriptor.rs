@@ -0,0 +1,554 @@
+//! HID report descriptor parsing.
+//!
+//! The report descriptor describes the layout of every report a device
+//! sends: which bits carry which usage (Mute, Volume Increment, Hook
+//! Switch...). Parsing it lets reports be decoded into named usages instead
+//! of being compared byte by byte.
+
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::gesture::ButtonEdge;
+
+// Usage pages with named usages
+const PAGE_TELEPHONY: u16 = 0x0b;
+const PAGE_BUTTON: u16 = 0x09;
+const PAGE_CONSUMER: u16 = 0x0c;
+const PAGE_VENDOR_FIRST: u16 = 0xff00;
+
+// Ranges larger than this are truncated when usages are expanded
+const MAX_USAGE_RANGE: u32 = 0x1000;
+// Descriptors come from the device; larger sizes and counts are rejected
+// rather than decoded value by value
+const MAX_REPORT_SIZE: usize = 256;
+const MAX_REPORT_COUNT: usize = 4096;
+
+/// A usage page and usage ID pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Usage {
+    pub page: u16,
+    pub id: u16,
+}
+
+impl Usage {
+    pub fn new(page: u16, id: u16) -> Self {
+        Usage { page, id }
+    }
+}
+
+impl fmt::Display for Usage {
+    /// Readable name, also used to bind actions to the usage
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match (self.page, self.id) {
+            (PAGE_TELEPHONY, 0x07) => Some("Programmable Button"),
+            (PAGE_TELEPHONY, 0x20) => Some("Hook Switch"),
+            (PAGE_TELEPHONY, 0x21) => Some("Flash"),
+            (PAGE_TELEPHONY, 0x24) => Some("Redial"),
+            (PAGE_TELEPHONY, 0x26) => Some("Drop"),
+            (PAGE_TELEPHONY, 0x2f) => Some("Phone Mute"),
+            (PAGE_CONSUMER, 0xb0) => Some("Play"),
+            (PAGE_CONSUMER, 0xb1) => Some("Pause"),
+            (PAGE_CONSUMER, 0xb5) => Some("Scan Next Track"),
+            (PAGE_CONSUMER, 0xb6) => Some("Scan Previous Track"),
+            (PAGE_CONSUMER, 0xb7) => Some("Stop"),
+            (PAGE_CONSUMER, 0xcd) => Some("Play/Pause"),
+            (PAGE_CONSUMER, 0xe2) => Some("Mute"),
+            (PAGE_CONSUMER, 0xe9) => Some("Volume Increment"),
+            (PAGE_CONSUMER, 0xea) => Some("Volume Decrement"),
+            _ => None,
+        };
+
+        match name {
+            Some(name) => write!(f, "{}", name),
+            None if self.page == PAGE_BUTTON => write!(f, "Button {}", self.id),
+            None if self.page >= PAGE_VENDOR_FIRST => write!(f, "Vendor {:04X}:{:04X}", self.page, self.id),
+            None => write!(f, "Usage {:04X}:{:04X}", self.page, self.id),
+        }
+    }
+}
+
+/// One Input main item: `report_count` values of `report_size` bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportField {
+    pub report_id: u8,
+    /// Position of the first value, in bits from the start of the payload
+    /// (after the report ID)
+    pub bit_offset: usize,
+    pub report_size: usize,
+    pub report_count: usize,
+    pub logical_minimum: i32,
+    pub logical_maximum: i32,
+    /// Padding or other constant data
+    pub constant: bool,
+    /// One value per usage; otherwise an array of selected usage indices
+    pub variable: bool,
+    pub relative: bool,
+    pub usages: Vec<Usage>,
+}
+
+impl ReportField {
+    /// Usages that behave like buttons: on/off variables and array entries
+    pub fn is_button(&self) -> bool {
+        !self.constant
+            && (!self.variable || (self.logical_minimum == 0 && self.logical_maximum == 1 && !self.relative))
+    }
+
+    fn value(&self, payload: &[u8], index: usize) -> Option<i32> {
+        let start = self.bit_offset + index * self.report_size;
+        if self.report_size == 0 || self.report_size > 32 || start + self.report_size > payload.len() * 8 {
+            return None;
+        }
+
+        let mut raw: u32 = 0;
+        for bit in 0..self.report_size {
+            let position = start + bit;
+            if payload[position / 8] & (1 << (position % 8)) != 0 {
+                raw |= 1 << bit;
+            }
+        }
+
+        // Values are signed only when the logical range includes negatives
+        if self.logical_minimum < 0 && self.report_size < 32 && raw & (1 << (self.report_size - 1)) != 0 {
+            Some((raw | !((1u32 << self.report_size) - 1)) as i32)
+        } else {
+            Some(raw as i32)
+        }
+    }
+
+    /// Number of values `payload` is long enough to hold
+    fn value_count(&self, payload: &[u8]) -> usize {
+        if self.report_size == 0 {
+            return 0;
+        }
+        let available = (payload.len() * 8).saturating_sub(self.bit_offset) / self.report_size;
+        self.report_count.min(available)
+    }
+
+    fn usage(&self, index: usize) -> Option<Usage> {
+        self.usages.get(index).or(self.usages.last()).copied()
+    }
+}
+
+/// Usages decoded from one input report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodedReport {
+    /// Button usages that are currently down
+    pub pressed: Vec<Usage>,
+    /// Every button usage this report carries, pressed or not
+    pub buttons: Vec<Usage>,
+    /// Values of usages that are not buttons, such as volume or vendor data
+    pub values: Vec<(Usage, i32)>,
+}
+
+/// Parsed report descriptor
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDescriptor {
+    pub fields: Vec<ReportField>,
+    /// Whether reports start with a report ID byte
+    pub uses_report_ids: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_minimum: i32,
+    logical_maximum: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+#[derive(Debug, Default)]
+struct LocalState {
+    usages: Vec<u32>,
+    usage_minimum: Option<u32>,
+    usage_maximum: Option<u32>,
+}
+
+impl LocalState {
+    /// Resolve usages against `usage_page`; 4 byte usages carry their own page
+    fn resolve(&self, usage_page: u16) -> Vec<Usage> {
+        let resolve = |usage: u32, extended: bool| {
+            if extended {
+                Usage::new((usage >> 16) as u16, usage as u16)
+            } else {
+                Usage::new(usage_page, usage as u16)
+            }
+        };
+
+        let mut usages: Vec<Usage> = self.usages.iter()
+            .map(|&usage| resolve(usage, usage > 0xffff))
+            .collect();
+
+        if let (Some(minimum), Some(maximum)) = (self.usage_minimum, self.usage_maximum) {
+            let extended = minimum > 0xffff;
+            let maximum = maximum.min(minimum.saturating_add(MAX_USAGE_RANGE));
+            usages.extend((minimum..=maximum).map(|usage| resolve(usage, extended)));
+        }
+
+        usages
+    }
+}
+
+impl ReportDescriptor {
+    pub fn parse(descriptor: &[u8]) -> Result<Self> {
+        let mut result = ReportDescriptor::default();
+        let mut global = GlobalState::default();
+        let mut global_stack = Vec::new();
+        let mut local = LocalState::default();
+        let mut collection_depth = 0usize;
+        // Bits used so far by the input report with each ID
+        let mut input_bits: HashMap<u8, usize> = HashMap::new();
+
+        let mut position = 0;
+        while position < descriptor.len() {
+            let prefix = descriptor[position];
+
+            if prefix == 0xfe {
+                // Long item: data size and tag follow; no long items are defined
+                let size = *descriptor.get(position + 1)
+                    .ok_or_else(|| anyhow::anyhow!("Truncated long item at offset {}", position))? as usize;
+                position += 3 + size;
+                if position > descriptor.len() {
+                    return Err(anyhow::anyhow!("Truncated long item"));
+                }
+                continue;
+            }
+
+            let size = match prefix & 0x03 {
+                3 => 4,
+                size => size as usize,
+            };
+            let item_type = (prefix >> 2) & 0x03;
+            let tag = prefix >> 4;
+
+            let data = descriptor.get(position + 1..position + 1 + size)
+                .ok_or_else(|| anyhow::anyhow!("Truncated item {:02x} at offset {}", prefix, position))?;
+            let unsigned = data.iter().rev().fold(0u32, |value, &byte| (value << 8) | byte as u32);
+            let signed = match size {
+                1 => unsigned as u8 as i8 as i32,
+                2 => unsigned as u16 as i16 as i32,
+                _ => unsigned as i32,
+            };
+            position += 1 + size;
+
+            match (item_type, tag) {
+                // Main items
+                (0, 0x8) => {
+                    let usages = local.resolve(global.usage_page);
+                    let bits = input_bits.entry(global.report_id).or_insert(0);
+                    result.fields.push(ReportField {
+                        report_id: global.report_id,
+                        bit_offset: *bits,
+                        report_size: global.report_size,
+                        report_count: global.report_count,
+                        logical_minimum: global.logical_minimum,
+                        logical_maximum: global.logical_maximum,
+                        constant: unsigned & 0x01 != 0,
+                        variable: unsigned & 0x02 != 0,
+                        relative: unsigned & 0x04 != 0,
+                        usages,
+                    });
+                    let field_bits = global.report_size.checked_mul(global.report_count)
+                        .ok_or_else(|| anyhow::anyhow!("Input item at offset {} is too large", position - 1 - size))?;
+                    *bits = bits.saturating_add(field_bits);
+                    local = LocalState::default();
+                }
+                (0, 0xa) => {
+                    collection_depth += 1;
+                    local = LocalState::default();
+                }
+                (0, 0xc) => {
+                    collection_depth = collection_depth.checked_sub(1)
+                        .ok_or_else(|| anyhow::anyhow!("End Collection without Collection at offset {}", position - 1 - size))?;
+                    local = LocalState::default();
+                }
+                // Output and Feature reports are not read
+                (0, _) => local = LocalState::default(),
+
+                // Global items
+                (1, 0x0) => global.usage_page = unsigned as u16,
+                (1, 0x1) => global.logical_minimum = signed,
+                (1, 0x2) => {
+                    // Devices often write an unsigned maximum such as 0xff in one byte
+                    global.logical_maximum = if signed < global.logical_minimum { unsigned as i32 } else { signed };
+                }
+                (1, 0x7) => {
+                    if unsigned as usize > MAX_REPORT_SIZE {
+                        return Err(anyhow::anyhow!("Report Size {} is too large", unsigned));
+                    }
+                    global.report_size = unsigned as usize;
+                }
+                (1, 0x8) => {
+                    if unsigned == 0 || unsigned > 0xff {
+                        return Err(anyhow::anyhow!("Invalid report ID {}", unsigned));
+                    }
+                    global.report_id = unsigned as u8;
+                    result.uses_report_ids = true;
+                }
+                (1, 0x9) => {
+                    if unsigned as usize > MAX_REPORT_COUNT {
+                        return Err(anyhow::anyhow!("Report Count {} is too large", unsigned));
+                    }
+                    global.report_count = unsigned as usize;
+                }
+                (1, 0xa) => global_stack.push(global.clone()),
+                (1, 0xb) => {
+                    global = global_stack.pop()
+                        .ok_or_else(|| anyhow::anyhow!("Pop without Push at offset {}", position - 1 - size))?;
+                }
+                (1, _) => {}
+
+                // Local items
+                (2, 0x0) => local.usages.push(if size == 4 { unsigned } else { unsigned & 0xffff }),
+                (2, 0x1) => local.usage_minimum = Some(if size == 4 { unsigned } else { unsigned & 0xffff }),
+                (2, 0x2) => local.usage_maximum = Some(if size == 4 { unsigned } else { unsigned & 0xffff }),
+                (2, _) => {}
+
+                _ => return Err(anyhow::anyhow!("Reserved item {:02x} at offset {}", prefix, position - 1 - size)),
+            }
+        }
+
+        if collection_depth != 0 {
+            return Err(anyhow::anyhow!("Unterminated collection"));
+        }
+
+        Ok(result)
+    }
+
+    /// Report IDs of the input reports described
+    pub fn report_ids(&self) -> Vec<u8> {
+        let mut ids: Vec<u8> = self.fields.iter().map(|field| field.report_id).collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    /// Every button usage any input report can carry
+    pub fn button_usages(&self) -> Vec<Usage> {
+        let mut usages: Vec<Usage> = self.fields.iter()
+            .filter(|field| field.is_button())
+            .flat_map(|field| field.usages.iter().copied())
+            .filter(|usage| usage.id != 0)
+            .collect();
+        usages.sort();
+        usages.dedup();
+        usages
+    }
+
+    /// Decode a raw report (starting with the report ID when the device
+    /// uses them). Reports the descriptor does not describe decode to nothing.
+    pub fn decode(&self, report: &[u8]) -> DecodedReport {
+        let mut decoded = DecodedReport::default();
+        let (report_id, payload) = if self.uses_report_ids {
+            match report.split_first() {
+                Some((&report_id, payload)) => (report_id, payload),
+                None => return decoded,
+            }
+        } else {
+            (0, report)
+        };
+
+        for field in self.fields.iter().filter(|field| field.report_id == report_id && !field.constant) {
+            if field.variable {
+                for index in 0..field.value_count(payload) {
+                    let (Some(usage), Some(value)) = (field.usage(index), field.value(payload, index)) else {
+                        continue;
+                    };
+                    if field.is_button() {
+                        decoded.buttons.push(usage);
+                        if value != 0 {
+                            decoded.pressed.push(usage);
+                        }
+                    } else {
+                        decoded.values.push((usage, value));
+                    }
+                }
+            } else {
+                // Arrays list the indices of the usages that are down
+                decoded.buttons.extend(field.usages.iter().filter(|usage| usage.id != 0));
+                for index in 0..field.value_count(payload) {
+                    let Some(value) = field.value(payload, index) else {
+                        continue;
+                    };
+                    if value < field.logical_minimum || value > field.logical_maximum {
+                        continue;
+                    }
+                    match field.usages.get((value - field.logical_minimum) as usize) {
+                        Some(usage) if usage.id != 0 => decoded.pressed.push(*usage),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        decoded.buttons.sort();
+        decoded.buttons.dedup();
+        decoded.pressed.sort();
+        decoded.pressed.dedup();
+        decoded
+    }
+}
+
+/// Turns decoded reports of one device into button press and release edges
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    down: HashSet<Usage>,
+}
+
+impl UsageTracker {
+    pub fn update(&mut self, decoded: &DecodedReport) -> Vec<(Usage, ButtonEdge)> {
+        let mut edges = Vec::new();
+
+        for usage in &decoded.buttons {
+            let pressed = decoded.pressed.contains(usage);
+            if pressed && self.down.insert(*usage) {
+                edges.push((*usage, ButtonEdge::Pressed));
+            } else if !pressed && self.down.remove(usage) {
+                edges.push((*usage, ButtonEdge::Released));
+            }
+        }
+
+        edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Telephony (ID 1), consumer control (ID 2) and vendor (ID 4) reports,
+    // laid out like a USB speakerphone
+    const SPEAKERPHONE: &[u8] = include_bytes!("../tests/descriptors/speakerphone.bin");
+    // Media keys sent as a 16 bit consumer control array
+    const CONSUMER_ARRAY: &[u8] = include_bytes!("../tests/descriptors/consumer_array.bin");
+    // Boot keyboard without report IDs
+    const BOOT_KEYBOARD: &[u8] = include_bytes!("../tests/descriptors/boot_keyboard.bin");
+
+    const MUTE: Usage = Usage { page: PAGE_CONSUMER, id: 0xe2 };
+    const VOLUME_UP: Usage = Usage { page: PAGE_CONSUMER, id: 0xe9 };
+    const PHONE_MUTE: Usage = Usage { page: PAGE_TELEPHONY, id: 0x2f };
+
+    #[test]
+    fn test_parse_speakerphone_layout() {
+        let descriptor = ReportDescriptor::parse(SPEAKERPHONE).unwrap();
+        assert!(descriptor.uses_report_ids);
+        assert_eq!(descriptor.report_ids(), vec![1, 2, 4]);
+
+        // Output reports (LEDs) are skipped; padding is kept as constant fields
+        let telephony: Vec<&ReportField> = descriptor.fields.iter().filter(|field| field.report_id == 1).collect();
+        assert_eq!(telephony.len(), 2);
+        assert_eq!(telephony[0].usages, vec![
+            Usage::new(PAGE_TELEPHONY, 0x20), PHONE_MUTE, Usage::new(PAGE_TELEPHONY, 0x21),
+        ]);
+        assert!(telephony[1].constant);
+        assert_eq!(telephony[1].bit_offset, 3);
+
+        let vendor = descriptor.fields.iter().find(|field| field.report_id == 4).unwrap();
+        assert_eq!((vendor.report_size, vendor.report_count), (8, 15));
+        assert_eq!(vendor.logical_maximum, 255);
+        assert!(!vendor.is_button());
+
+        let names: Vec<String> = descriptor.button_usages().iter().map(|usage| usage.to_string()).collect();
+        assert_eq!(names, vec![
+            "Hook Switch", "Flash", "Phone Mute", "Play/Pause", "Mute", "Volume Increment", "Volume Decrement",
+        ]);
+    }
+
+    #[test]
+    fn test_decode_variable_buttons() {
+        let descriptor = ReportDescriptor::parse(SPEAKERPHONE).unwrap();
+
+        let decoded = descriptor.decode(&[0x02, 0b0000_0100]);
+        assert_eq!(decoded.pressed, vec![MUTE]);
+        assert_eq!(decoded.buttons.len(), 4);
+
+        let decoded = descriptor.decode(&[0x01, 0b0000_0010]);
+        assert_eq!(decoded.pressed, vec![PHONE_MUTE]);
+
+        // The AI button report is vendor data, not a button
+        let decoded = descriptor.decode(&[0x04, 0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(decoded.pressed.is_empty());
+        assert_eq!(decoded.values.len(), 15);
+        assert_eq!(decoded.values[0], (Usage::new(0xff00, 0x01), 0xb2));
+        assert_eq!(decoded.values[0].0.to_string(), "Vendor FF00:0001");
+
+        // Unknown report IDs and short reports decode to nothing
+        assert_eq!(descriptor.decode(&[0x07, 0xff]), DecodedReport::default());
+        assert!(descriptor.decode(&[0x04, 0xb2]).values.len() < 15);
+    }
+
+    #[test]
+    fn test_decode_consumer_array() {
+        let descriptor = ReportDescriptor::parse(CONSUMER_ARRAY).unwrap();
+        assert_eq!(descriptor.fields.len(), 1);
+        assert!(!descriptor.fields[0].variable);
+
+        let decoded = descriptor.decode(&[0x03, 0xe9, 0x00]);
+        assert_eq!(decoded.pressed, vec![VOLUME_UP]);
+        assert!(descriptor.decode(&[0x03, 0x00, 0x00]).pressed.is_empty());
+
+        // Usage 0x0221 (AC Search) has no name
+        let decoded = descriptor.decode(&[0x03, 0x21, 0x02]);
+        assert_eq!(decoded.pressed[0].to_string(), "Usage 000C:0221");
+    }
+
+    #[test]
+    fn test_decode_report_without_ids() {
+        let descriptor = ReportDescriptor::parse(BOOT_KEYBOARD).unwrap();
+        assert!(!descriptor.uses_report_ids);
+
+        // Left Shift + "a"
+        let decoded = descriptor.decode(&[0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(decoded.pressed, vec![Usage::new(0x07, 0x04), Usage::new(0x07, 0xe1)]);
+    }
+
+    #[test]
+    fn test_tracker_emits_press_and_release() {
+        let descriptor = ReportDescriptor::parse(SPEAKERPHONE).unwrap();
+        let mut tracker = UsageTracker::default();
+
+        assert_eq!(tracker.update(&descriptor.decode(&[0x02, 0b0100])), vec![(MUTE, ButtonEdge::Pressed)]);
+        // Still held: no new edge; a second button goes down
+        assert_eq!(tracker.update(&descriptor.decode(&[0x02, 0b0101])), vec![(VOLUME_UP, ButtonEdge::Pressed)]);
+        // Reports for other IDs don't release anything
+        assert!(tracker.update(&descriptor.decode(&[0x01, 0b0000])).is_empty());
+
+        let mut edges = tracker.update(&descriptor.decode(&[0x02, 0b0000]));
+        edges.sort_by_key(|(usage, _)| *usage);
+        assert_eq!(edges, vec![(MUTE, ButtonEdge::Released), (VOLUME_UP, ButtonEdge::Released)]);
+    }
+
+    #[test]
+    fn test_decode_stops_at_end_of_payload() {
+        // 4096 one bit buttons, in a one byte report
+        let descriptor = ReportDescriptor::parse(&[
+            0x05, 0x09, 0x19, 0x01, 0x2a, 0x00, 0x10, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x96, 0x00, 0x10, 0x81, 0x02,
+        ]).unwrap();
+        assert_eq!(descriptor.fields[0].report_count, 4096);
+
+        let decoded = descriptor.decode(&[0b0000_0101]);
+        assert_eq!(decoded.buttons.len(), 8);
+        assert_eq!(decoded.pressed, vec![Usage::new(PAGE_BUTTON, 1), Usage::new(PAGE_BUTTON, 3)]);
+    }
+
+    #[test]
+    fn test_reject_malformed_descriptors() {
+        // Logical Maximum with its data byte missing
+        assert!(ReportDescriptor::parse(&[0x05, 0x0c, 0x25]).is_err());
+        // Collection never closed
+        assert!(ReportDescriptor::parse(&[0x05, 0x0c, 0x09, 0x01, 0xa1, 0x01]).is_err());
+        // End Collection and Pop without a matching opener
+        assert!(ReportDescriptor::parse(&[0xc0]).is_err());
+        assert!(ReportDescriptor::parse(&[0xb4]).is_err());
+        // Report ID 0 is reserved
+        assert!(ReportDescriptor::parse(&[0x85, 0x00]).is_err());
+        // Report Count of 4 billion one bit buttons
+        let huge_count = [0x05, 0x0c, 0x09, 0xe2, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x97, 0xff, 0xff, 0xff, 0xff, 0x81, 0x02];
+        let error = ReportDescriptor::parse(&huge_count).unwrap_err();
+        assert_eq!(error.to_string(), "Report Count 4294967295 is too large");
+
+        assert_eq!(ReportDescriptor::parse(&[]).unwrap(), ReportDescriptor::default());
+    }
+}
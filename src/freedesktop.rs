@@ -0,0 +1,319 @@
+//! Desktop notifications through the `org.freedesktop.Notifications` D-Bus
+//! service that GNOME, KDE, dunst, mako and most other Linux desktops
+//! provide.
+//!
+//! Talking to the service directly, rather than through `notify-send`, gives
+//! access to urgency, icons, expiry, replacing an earlier notification and
+//! buttons. The service reports which button was clicked; the name of the
+//! action behind it is sent to the assistant, which runs that action.
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::zvariant::Value;
+
+use crate::config::{NotificationButton, Urgency};
+use crate::notification::xml_text;
+
+const APP_NAME: &str = "SVBONY AI Assistant";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+/// A notification to show
+pub struct Notification<'a> {
+    pub title: &'a str,
+    pub message: &'a str,
+    /// Icon name from the icon theme or path of an image; empty for none
+    pub icon: &'a str,
+    pub urgency: Urgency,
+    /// Milliseconds until it disappears: -1 leaves it to the service, 0 keeps
+    /// it until it is dismissed
+    pub expire_timeout: i32,
+    /// Replaces the last notification shown with the same tag
+    pub tag: Option<&'a str>,
+    pub buttons: &'a [NotificationButton],
+}
+
+#[derive(Default)]
+struct Shown {
+    /// Notifications with buttons that are still open
+    with_buttons: HashSet<u32>,
+    /// Last notification shown for each tag
+    tags: HashMap<String, u32>,
+}
+
+pub struct Notifier {
+    proxy: NotificationsProxy<'static>,
+    /// Whether the service reads the message as markup
+    body_markup: bool,
+    shown: Arc<Mutex<Shown>>,
+}
+
+impl Notifier {
+    /// Connect to the notification service on the session bus. The names of
+    /// the actions behind clicked buttons are sent to `clicks`.
+    pub async fn connect(clicks: UnboundedSender<String>) -> Result<Self> {
+        let connection = zbus::Connection::session().await
+            .context("Failed to connect to the session bus")?;
+        Self::with_connection(&connection, clicks).await
+    }
+
+    pub async fn with_connection(connection: &zbus::Connection, clicks: UnboundedSender<String>) -> Result<Self> {
+        let proxy = NotificationsProxy::new(connection).await?;
+        let capabilities = proxy.get_capabilities().await
+            .context("The notification service is not available")?;
+        debug!("Notification service capabilities: {:?}", capabilities);
+
+        // Subscribe before anything is shown so no click is missed
+        let mut invoked = proxy.receive_action_invoked().await?;
+        let mut closed = proxy.receive_notification_closed().await?;
+        let shown = Arc::new(Mutex::new(Shown::default()));
+
+        let listener_shown = shown.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(signal) = invoked.next() => {
+                        let Ok(args) = signal.args() else { continue };
+                        // Other applications' notifications are reported too
+                        if listener_shown.lock().unwrap().with_buttons.contains(&args.id) {
+                            debug!("Notification button {} clicked", args.action_key);
+                            let _ = clicks.send(args.action_key);
+                        }
+                    }
+                    Some(signal) = closed.next() => {
+                        if let Ok(args) = signal.args() {
+                            listener_shown.lock().unwrap().with_buttons.remove(&args.id);
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+
+        Ok(Notifier {
+            proxy,
+            body_markup: capabilities.iter().any(|capability| capability == "body-markup"),
+            shown,
+        })
+    }
+
+    /// Show `notification` and return its id
+    pub async fn notify(&self, notification: &Notification<'_>) -> Result<u32> {
+        let replaces_id = notification.tag
+            .and_then(|tag| self.shown.lock().unwrap().tags.get(tag).copied())
+            .unwrap_or(0);
+        // Keys and labels in turn; the key is the name of the action to run
+        let actions: Vec<&str> = notification.buttons.iter()
+            .flat_map(|button| [button.action.as_str(), button.label.as_str()])
+            .collect();
+        let hints = HashMap::from([("urgency", Value::U8(urgency_level(notification.urgency)))]);
+        // The message is shown as it is, not as markup
+        let message = if self.body_markup { xml_text(notification.message) } else { notification.message.to_string() };
+
+        let id = self.proxy.notify(
+            APP_NAME,
+            replaces_id,
+            notification.icon,
+            notification.title,
+            &message,
+            &actions,
+            hints,
+            notification.expire_timeout,
+        ).await.context("Failed to show notification")?;
+
+        let mut shown = self.shown.lock().unwrap();
+        if let Some(tag) = notification.tag {
+            shown.tags.insert(tag.to_string(), id);
+        }
+        if notification.buttons.is_empty() {
+            // The replaced notification may have had buttons
+            shown.with_buttons.remove(&id);
+        } else {
+            shown.with_buttons.insert(id);
+        }
+        Ok(id)
+    }
+}
+
+fn urgency_level(urgency: Urgency) -> u8 {
+    match urgency {
+        Urgency::Low => 0,
+        Urgency::Normal => 1,
+        Urgency::Critical => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::time::Duration;
+    use zbus::zvariant::OwnedValue;
+
+    const PATH: &str = "/org/freedesktop/Notifications";
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+
+    /// A dbus-daemon of the test's own, stopped when dropped
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+            Some(PrivateBus { daemon, address: address.trim().to_string() })
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[derive(Debug)]
+    struct Call {
+        replaces_id: u32,
+        icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        urgency: Option<u8>,
+        expire_timeout: i32,
+    }
+
+    /// Notification service that records what it is asked to show
+    #[derive(Clone, Default)]
+    struct StubServer {
+        calls: Arc<Mutex<Vec<Call>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl StubServer {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: String,
+            replaces_id: u32,
+            app_icon: String,
+            summary: String,
+            body: String,
+            actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            expire_timeout: i32,
+        ) -> u32 {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(Call {
+                replaces_id,
+                icon: app_icon,
+                summary,
+                body,
+                actions,
+                urgency: hints.get("urgency").and_then(|value| value.downcast_ref::<u8>().ok()),
+                expire_timeout,
+            });
+            if replaces_id != 0 { replaces_id } else { calls.len() as u32 }
+        }
+
+        fn get_capabilities(&self) -> Vec<String> {
+            vec!["actions".to_string(), "body".to_string(), "body-markup".to_string()]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notifications_over_dbus() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon is not installed, skipping");
+            return;
+        };
+
+        let stub = StubServer::default();
+        let server = zbus::connection::Builder::address(bus.address.as_str()).unwrap()
+            .name(INTERFACE).unwrap()
+            .serve_at(PATH, stub.clone()).unwrap()
+            .build().await.unwrap();
+        let client = zbus::connection::Builder::address(bus.address.as_str()).unwrap()
+            .build().await.unwrap();
+        let (clicks, mut clicked) = tokio::sync::mpsc::unbounded_channel();
+        let notifier = Notifier::with_connection(&client, clicks).await.unwrap();
+
+        let buttons = [NotificationButton { label: "Open".to_string(), action: "Open transcript".to_string() }];
+        let notification = Notification {
+            title: "Recording",
+            message: "Saved <b>1</b> file",
+            icon: "media-record",
+            urgency: Urgency::Critical,
+            expire_timeout: 3000,
+            tag: Some("recording"),
+            buttons: &buttons,
+        };
+        let first = notifier.notify(&notification).await.unwrap();
+        let second = notifier.notify(&Notification { buttons: &[], ..notification }).await.unwrap();
+        assert_eq!(first, second);
+
+        {
+            let calls = stub.calls.lock().unwrap();
+            assert_eq!(calls[0].summary, "Recording");
+            assert_eq!(calls[0].body, "Saved &lt;b&gt;1&lt;/b&gt; file");
+            assert_eq!(calls[0].icon, "media-record");
+            assert_eq!(calls[0].actions, vec!["Open transcript", "Open"]);
+            assert_eq!(calls[0].urgency, Some(2));
+            assert_eq!(calls[0].expire_timeout, 3000);
+            assert_eq!(calls[0].replaces_id, 0);
+            // The same tag replaces the first notification
+            assert_eq!(calls[1].replaces_id, first);
+        }
+
+        let buttons = [NotificationButton { label: "Retry".to_string(), action: "Retry recording".to_string() }];
+        let third = notifier.notify(&Notification { tag: None, buttons: &buttons, ..notification }).await.unwrap();
+        assert_ne!(third, first);
+
+        // Clicks on other applications' notifications, and on a notification
+        // replaced by one without buttons, are ignored
+        server.emit_signal(None::<&str>, PATH, INTERFACE, "ActionInvoked", &(99u32, "Other")).await.unwrap();
+        server.emit_signal(None::<&str>, PATH, INTERFACE, "ActionInvoked", &(first, "Open transcript")).await.unwrap();
+        server.emit_signal(None::<&str>, PATH, INTERFACE, "ActionInvoked", &(third, "Retry recording")).await.unwrap();
+        let name = tokio::time::timeout(Duration::from_secs(5), clicked.recv()).await.unwrap();
+        assert_eq!(name.as_deref(), Some("Retry recording"));
+    }
+}
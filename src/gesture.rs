@@ -0,0 +1,351 @@
+use log::debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::{Gesture, GestureSettings};
+use crate::input::DeviceIdentity;
+
+/// Physical state change of a button, derived from a matched HID report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEdge {
+    Pressed,
+    Released,
+}
+
+/// A recognized gesture on one button of one device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GestureEvent {
+    pub device: DeviceIdentity,
+    pub button: String,
+    pub gesture: Gesture,
+    /// Number of presses in the sequence that produced this event
+    pub press_count: u32,
+    pub timestamp: Instant,
+}
+
+#[derive(Debug)]
+struct ButtonState {
+    device: DeviceIdentity,
+    pressed_at: Option<Instant>,
+    long_press_fired: bool,
+    press_count: u32,
+    last_release: Option<Instant>,
+}
+
+/// Turns button edges into single/double/triple/long-press gestures.
+///
+/// Press and Release events are emitted immediately. Multi-press gestures
+/// are emitted once the multi-press window expires after the last release,
+/// or right away when as many presses were seen as the button's bindings
+/// need, so buttons that only have single-press bindings fire without any
+/// added latency.
+/// Long presses are detected by [`GestureDetector::poll`], which must be
+/// called regularly.
+#[derive(Debug)]
+pub struct GestureDetector {
+    multi_press_window: Duration,
+    long_press: Duration,
+    // Presses the bindings of each button need, by lowercased button name;
+    // 1 for buttons without multi-press bindings
+    max_press_counts: HashMap<String, u32>,
+    // Keyed by device path and button name
+    buttons: HashMap<(String, String), ButtonState>,
+}
+
+impl GestureDetector {
+    pub fn new(settings: &GestureSettings, max_press_counts: HashMap<String, u32>) -> Self {
+        GestureDetector {
+            multi_press_window: Duration::from_millis(settings.multi_press_window_ms),
+            long_press: Duration::from_millis(settings.long_press_ms),
+            max_press_counts,
+            buttons: HashMap::new(),
+        }
+    }
+
+    /// Take new timing settings and press counts after the configuration was
+    /// reloaded. Buttons in the middle of a gesture keep their state.
+    pub fn reconfigure(&mut self, settings: &GestureSettings, max_press_counts: HashMap<String, u32>) {
+        self.multi_press_window = Duration::from_millis(settings.multi_press_window_ms);
+        self.long_press = Duration::from_millis(settings.long_press_ms);
+        self.max_press_counts = max_press_counts;
+    }
+
+    /// Largest press count the gestures bound to each button need, keyed by
+    /// lowercased button name; used to avoid waiting for presses that
+    /// nothing is bound to
+    pub fn max_press_counts<'a>(bindings: impl Iterator<Item = (&'a str, &'a Gesture)>) -> HashMap<String, u32> {
+        let mut counts = HashMap::new();
+        for (button, gesture) in bindings {
+            if let Some(count) = gesture.press_count() {
+                let max = counts.entry(button.to_lowercase()).or_insert(1);
+                *max = (*max).max(count);
+            }
+        }
+        counts
+    }
+
+    fn max_press_count(&self, button: &str) -> u32 {
+        self.max_press_counts.get(&button.to_lowercase()).copied().unwrap_or(1).clamp(1, 3)
+    }
+
+    pub fn on_edge(&mut self, device: &DeviceIdentity, button: &str, edge: ButtonEdge, timestamp: Instant) -> Vec<GestureEvent> {
+        let mut events = Vec::new();
+        let max_press_count = self.max_press_count(button);
+        let state = self.buttons.entry((device.path.clone(), button.to_string()))
+            .or_insert_with(|| ButtonState {
+                device: device.clone(),
+                pressed_at: None,
+                long_press_fired: false,
+                press_count: 0,
+                last_release: None,
+            });
+
+        match edge {
+            ButtonEdge::Pressed => {
+                if state.pressed_at.is_some() {
+                    // Repeated press report while held
+                    return events;
+                }
+
+                state.pressed_at = Some(timestamp);
+                state.long_press_fired = false;
+                events.push(Self::event(&state.device, button, Gesture::Press, state.press_count + 1, timestamp));
+            }
+            ButtonEdge::Released => {
+                if state.pressed_at.take().is_none() {
+                    return events;
+                }
+
+                events.push(Self::event(&state.device, button, Gesture::Release, state.press_count + 1, timestamp));
+
+                if state.long_press_fired {
+                    // A long press ends the sequence, it never counts as a click
+                    state.press_count = 0;
+                    state.last_release = None;
+                    return events;
+                }
+
+                state.press_count += 1;
+                state.last_release = Some(timestamp);
+
+                if state.press_count >= max_press_count {
+                    if let Some(gesture) = Gesture::from_press_count(state.press_count) {
+                        events.push(Self::event(&state.device, button, gesture, state.press_count, timestamp));
+                    }
+                    state.press_count = 0;
+                    state.last_release = None;
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Earliest time at which [`GestureDetector::poll`] may emit a gesture,
+    /// if any timer is running
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.buttons.values()
+            .filter_map(|state| match state.pressed_at {
+                Some(pressed_at) if !state.long_press_fired => Some(pressed_at + self.long_press),
+                Some(_) => None,
+                None => state.last_release.map(|last_release| last_release + self.multi_press_window),
+            })
+            .min()
+    }
+
+    /// Emit gestures whose timers expired by `now`: long presses of held
+    /// buttons and multi-press sequences whose window has closed
+    pub fn poll(&mut self, now: Instant) -> Vec<GestureEvent> {
+        let mut events = Vec::new();
+
+        for ((_, button), state) in self.buttons.iter_mut() {
+            if let Some(pressed_at) = state.pressed_at {
+                if !state.long_press_fired && now.duration_since(pressed_at) >= self.long_press {
+                    debug!("Long press on button {} of {}", button, state.device);
+                    state.long_press_fired = true;
+                    events.push(Self::event(&state.device, button, Gesture::LongPress, state.press_count + 1, now));
+                }
+                continue;
+            }
+
+            if let Some(last_release) = state.last_release {
+                if now.duration_since(last_release) >= self.multi_press_window {
+                    if let Some(gesture) = Gesture::from_press_count(state.press_count) {
+                        events.push(Self::event(&state.device, button, gesture, state.press_count, now));
+                    }
+                    state.press_count = 0;
+                    state.last_release = None;
+                }
+            }
+        }
+
+        events
+    }
+
+    fn event(device: &DeviceIdentity, button: &str, gesture: Gesture, press_count: u32, timestamp: Instant) -> GestureEvent {
+        GestureEvent {
+            device: device.clone(),
+            button: button.to_string(),
+            gesture,
+            press_count,
+            timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::tests::test_identity;
+
+    fn m6() -> DeviceIdentity {
+        test_identity("scripted/0", 0x364d, "M6", None)
+    }
+
+    /// Detector whose AI button has bindings for `max_press_count` presses
+    fn detector(max_press_count: u32) -> GestureDetector {
+        let max_press_counts = HashMap::from([("ai".to_string(), max_press_count)]);
+        GestureDetector::new(&GestureSettings::default(), max_press_counts)
+    }
+
+    fn gestures(events: &[GestureEvent]) -> Vec<Gesture> {
+        events.iter().map(|event| event.gesture).collect()
+    }
+
+    fn click(detector: &mut GestureDetector, at: Instant) -> Vec<GestureEvent> {
+        let mut events = detector.on_edge(&m6(), "AI", ButtonEdge::Pressed, at);
+        events.extend(detector.on_edge(&m6(), "AI", ButtonEdge::Released, at + Duration::from_millis(50)));
+        events
+    }
+
+    #[test]
+    fn test_single_press_fires_immediately_without_multi_press_bindings() {
+        let mut detector = detector(1);
+        let start = Instant::now();
+
+        let events = click(&mut detector, start);
+        assert_eq!(gestures(&events), vec![Gesture::Press, Gesture::Release, Gesture::Single]);
+        assert!(detector.poll(start + Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn test_single_press_waits_for_multi_press_window() {
+        let mut detector = detector(2);
+        let start = Instant::now();
+
+        let events = click(&mut detector, start);
+        assert_eq!(gestures(&events), vec![Gesture::Press, Gesture::Release]);
+
+        assert!(detector.poll(start + Duration::from_millis(200)).is_empty());
+        let events = detector.poll(start + Duration::from_millis(500));
+        assert_eq!(gestures(&events), vec![Gesture::Single]);
+    }
+
+    #[test]
+    fn test_double_and_triple_press() {
+        let mut detector = detector(3);
+        let start = Instant::now();
+
+        click(&mut detector, start);
+        click(&mut detector, start + Duration::from_millis(200));
+        let events = detector.poll(start + Duration::from_millis(1000));
+        assert_eq!(gestures(&events), vec![Gesture::Double]);
+        assert_eq!(events[0].press_count, 2);
+
+        let start = start + Duration::from_secs(2);
+        click(&mut detector, start);
+        click(&mut detector, start + Duration::from_millis(200));
+        let events = click(&mut detector, start + Duration::from_millis(400));
+        assert_eq!(gestures(&events), vec![Gesture::Press, Gesture::Release, Gesture::Triple]);
+    }
+
+    #[test]
+    fn test_next_deadline_follows_running_timers() {
+        let mut detector = detector(2);
+        let start = Instant::now();
+        assert_eq!(detector.next_deadline(), None);
+
+        detector.on_edge(&m6(), "AI", ButtonEdge::Pressed, start);
+        assert_eq!(detector.next_deadline(), Some(start + Duration::from_millis(800)));
+
+        let released = start + Duration::from_millis(100);
+        detector.on_edge(&m6(), "AI", ButtonEdge::Released, released);
+        assert_eq!(detector.next_deadline(), Some(released + Duration::from_millis(400)));
+
+        detector.poll(released + Duration::from_millis(400));
+        assert_eq!(detector.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_long_press_does_not_count_as_click() {
+        let mut detector = detector(2);
+        let start = Instant::now();
+
+        detector.on_edge(&m6(), "AI", ButtonEdge::Pressed, start);
+        assert!(detector.poll(start + Duration::from_millis(500)).is_empty());
+
+        let events = detector.poll(start + Duration::from_millis(900));
+        assert_eq!(gestures(&events), vec![Gesture::LongPress]);
+        // Fires only once per hold
+        assert!(detector.poll(start + Duration::from_millis(1500)).is_empty());
+
+        let events = detector.on_edge(&m6(), "AI", ButtonEdge::Released, start + Duration::from_millis(1600));
+        assert_eq!(gestures(&events), vec![Gesture::Release]);
+        assert!(detector.poll(start + Duration::from_secs(3)).is_empty());
+    }
+
+    #[test]
+    fn test_buttons_are_tracked_independently() {
+        let max_press_counts = HashMap::from([("ai".to_string(), 2), ("mute".to_string(), 2)]);
+        let mut detector = GestureDetector::new(&GestureSettings::default(), max_press_counts);
+        let start = Instant::now();
+
+        click(&mut detector, start);
+        detector.on_edge(&m6(), "Mute", ButtonEdge::Pressed, start + Duration::from_millis(100));
+        detector.on_edge(&m6(), "Mute", ButtonEdge::Released, start + Duration::from_millis(150));
+
+        let mut events = detector.poll(start + Duration::from_millis(700));
+        events.sort_by(|a, b| a.button.cmp(&b.button));
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].button.as_str(), events[0].gesture), ("AI", Gesture::Single));
+        assert_eq!((events[1].button.as_str(), events[1].gesture), ("Mute", Gesture::Single));
+    }
+
+    #[test]
+    fn test_devices_are_tracked_independently() {
+        let mut detector = detector(2);
+        let start = Instant::now();
+        let omni = test_identity("scripted/1", 0x5053, "Omni2P", None);
+
+        // One press on each device is not a double press
+        click(&mut detector, start);
+        detector.on_edge(&omni, "AI", ButtonEdge::Pressed, start + Duration::from_millis(100));
+        detector.on_edge(&omni, "AI", ButtonEdge::Released, start + Duration::from_millis(150));
+
+        let events = detector.poll(start + Duration::from_millis(700));
+        assert_eq!(gestures(&events), vec![Gesture::Single, Gesture::Single]);
+        assert!(events.iter().any(|event| event.device == omni));
+    }
+
+    #[test]
+    fn test_max_press_count_from_bindings() {
+        let bound = [("AI", Gesture::Single), ("AI", Gesture::LongPress), ("ai", Gesture::Double), ("Mute", Gesture::Release)];
+        let counts = GestureDetector::max_press_counts(bound.iter().map(|(button, gesture)| (*button, gesture)));
+        assert_eq!(counts, HashMap::from([("ai".to_string(), 2)]));
+    }
+
+    #[test]
+    fn test_press_counts_are_per_button() {
+        let mut detector = detector(2);
+        let start = Instant::now();
+
+        // The AI button waits for a second press, Mute has nothing to wait for
+        let events = click(&mut detector, start);
+        assert_eq!(gestures(&events), vec![Gesture::Press, Gesture::Release]);
+
+        let mut events = detector.on_edge(&m6(), "Mute", ButtonEdge::Pressed, start);
+        events.extend(detector.on_edge(&m6(), "Mute", ButtonEdge::Released, start + Duration::from_millis(50)));
+        assert_eq!(gestures(&events), vec![Gesture::Press, Gesture::Release, Gesture::Single]);
+        assert_eq!(events[2].button, "Mute");
+    }
+}
@@ -1,33 +1,403 @@
 use anyhow::{Context, Result};
 use hidapi::{HidApi, HidDevice, DeviceInfo}; // Fixed: HidDeviceInfo -> DeviceInfo
-use log::{debug, info};
+use log::{debug, info, warn};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::config::ButtonSignature;
+use crate::descriptor::{ReportDescriptor, UsageTracker};
+use crate::gesture::ButtonEdge;
+use crate::input::{DeviceConnector, DeviceIdentity, HidApiConnector, InputSource};
+#[cfg(target_os = "linux")]
+use crate::hotplug::{HotplugAction, HotplugWatcher};
+
+// Reader threads block on the device for up to this long before checking
+// whether they were asked to stop
+const READER_TIMEOUT_MS: i32 = 1000;
+
+// With hotplug events available, periodic scans only catch missed events
+#[cfg(target_os = "linux")]
+const HOTPLUG_FALLBACK_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+// Delay before opening a newly added device node, so udev can set its permissions
+#[cfg(target_os = "linux")]
+const HOTPLUG_SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Events sent from the reader threads (and the hotplug watcher) to the
+/// async side
+#[derive(Debug, Clone)]
+pub enum MonitorEvent {
+    Report(DeviceReport),
+    /// A reader stopped or a hotplug event arrived; call
+    /// [`HidMonitor::maintain`] to reconnect or tear down devices
+    DevicesChanged,
+}
+
+/// A report together with the device it was read from
+#[derive(Debug, Clone)]
+pub struct DeviceReport {
+    pub device: DeviceIdentity,
+    pub report: HidReport,
+    /// Buttons pressed or released according to the device's report
+    /// descriptor, named after their usage ("Mute", "Hook Switch")
+    pub usage_edges: Vec<(String, ButtonEdge)>,
+}
+
+/// Dedicated thread that blocks on one device and forwards its reports
+struct DeviceReader {
+    stop: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceReader {
+    fn spawn(identity: DeviceIdentity, mut source: Box<dyn InputSource>, events: UnboundedSender<MonitorEvent>) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        let thread_finished = finished.clone();
+        let thread = thread::Builder::new()
+            .name(format!("hid-reader {}", identity.model))
+            .spawn(move || {
+                let mut buffer = [0u8; 1024]; // 1K buffer as specified
+                let descriptor = load_descriptor(source.as_ref(), &identity);
+                let mut tracker = UsageTracker::default();
+
+                while !thread_stop.load(Ordering::Relaxed) {
+                    match source.read_report(&mut buffer, READER_TIMEOUT_MS) {
+                        Ok(size) if size > 0 => {
+                            log_hid_data(&buffer, size);
+                            let usage_edges = match &descriptor {
+                                Some(descriptor) => decode_usage_edges(descriptor, &mut tracker, &buffer[..size]),
+                                None => Vec::new(),
+                            };
+                            if let Some(report) = parse_hid_report(&buffer[..size]) {
+                                let device_report = DeviceReport { device: identity.clone(), report, usage_edges };
+                                if events.send(MonitorEvent::Report(device_report)).is_err() {
+                                    // Nobody is listening any more
+                                    break;
+                                }
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!("Device monitoring error on {}: {}", identity, e);
+                            break;
+                        }
+                    }
+                }
+
+                thread_finished.store(true, Ordering::Relaxed);
+                let _ = events.send(MonitorEvent::DevicesChanged);
+                debug!("Reader for {} stopped", identity);
+            })
+            .context("Failed to spawn HID reader thread")?;
+
+        Ok(DeviceReader {
+            stop,
+            finished,
+            thread: Some(thread),
+        })
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Ask the thread to stop and wait for it
+    fn stop_and_join(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DeviceReader {
+    fn drop(&mut self) {
+        // Don't block here: the thread exits after its current read
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A device found by a scan, with its own reader and reconnect state
+struct MonitoredDevice {
+    identity: DeviceIdentity,
+    reader: Option<DeviceReader>,
+    last_connection_attempt: Option<Instant>,
+}
+
+impl MonitoredDevice {
+    fn is_connected(&self) -> bool {
+        self.reader.as_ref().is_some_and(|reader| !reader.is_finished())
+    }
+}
 
-#[derive(Debug)] // Added Debug derive
 pub struct HidMonitor {
     vendor_id: u16,
     product_ids: Vec<u16>,
     last_scan: Instant,
     scan_interval: Duration,
+    connector: Box<dyn DeviceConnector>,
+    devices: Vec<MonitoredDevice>,
+    last_connection_attempt: Option<Instant>,
+    scan_requested_at: Option<Instant>,
+    #[cfg(target_os = "linux")]
+    hotplug: Option<HotplugWatcher>,
+    events: UnboundedSender<MonitorEvent>,
+    events_receiver: Option<UnboundedReceiver<MonitorEvent>>,
+}
+
+impl fmt::Debug for HidMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HidMonitor")
+            .field("vendor_id", &self.vendor_id)
+            .field("product_ids", &self.product_ids)
+            .field("connector", &self.connector)
+            .field("connected", &self.connected_devices())
+            .finish()
+    }
 }
 
 impl HidMonitor {
     pub fn new(vendor_id: u16, product_id: u16) -> Result<Self> {
-        Ok(HidMonitor {
-            vendor_id,
-            product_ids: vec![product_id],
-            last_scan: Instant::now(),
-            scan_interval: Duration::from_secs(5),
-        })
+        Self::new_multi_pid(vendor_id, vec![product_id])
     }
 
     pub fn new_multi_pid(vendor_id: u16, product_ids: Vec<u16>) -> Result<Self> {
-        Ok(HidMonitor {
+        let names: Vec<String> = product_ids.iter().map(|pid| format!("{:04x}", pid)).collect();
+        let models: Vec<(u16, &str)> = product_ids.iter().copied()
+            .zip(names.iter().map(|name| name.as_str()))
+            .collect();
+        Self::new_with_models(vendor_id, &models)
+    }
+
+    /// Monitor every attached device whose product ID is listed in `models`,
+    /// naming each device after its model
+    pub fn new_with_models(vendor_id: u16, models: &[(u16, &str)]) -> Result<Self> {
+        let product_ids = models.iter().map(|(product_id, _)| *product_id).collect();
+        let connector = HidApiConnector::new(vendor_id, models);
+        Ok(Self::with_connector(vendor_id, product_ids, Box::new(connector)))
+    }
+
+    /// Create a monitor that obtains its input sources from `connector`
+    pub fn with_connector(vendor_id: u16, product_ids: Vec<u16>, connector: Box<dyn DeviceConnector>) -> Self {
+        let (events, events_receiver) = mpsc::unbounded_channel();
+
+        HidMonitor {
             vendor_id,
             product_ids,
             last_scan: Instant::now(),
             scan_interval: Duration::from_secs(5),
-        })
+            connector,
+            devices: Vec::new(),
+            last_connection_attempt: None,
+            scan_requested_at: None,
+            #[cfg(target_os = "linux")]
+            hotplug: None,
+            events,
+            events_receiver: Some(events_receiver),
+        }
+    }
+
+    /// Take the receiving end of the event channel. Reports from every
+    /// device reader arrive here; can only be taken once.
+    pub fn take_events(&mut self) -> Option<UnboundedReceiver<MonitorEvent>> {
+        self.events_receiver.take()
+    }
+
+    /// Listen for kernel hotplug events so devices are connected and torn
+    /// down as soon as they are plugged in or removed. Polling scans keep
+    /// running at a much lower rate as a fallback.
+    #[cfg(target_os = "linux")]
+    pub fn enable_hotplug(&mut self) -> Result<()> {
+        let events = self.events.clone();
+        let waker = Box::new(move || {
+            let _ = events.send(MonitorEvent::DevicesChanged);
+        });
+        let watcher = HotplugWatcher::start(self.vendor_id, self.product_ids.clone(), waker)?;
+        self.hotplug = Some(watcher);
+        Ok(())
+    }
+
+    #[cfg(all(test, target_os = "linux"))]
+    pub fn set_hotplug_watcher(&mut self, watcher: HotplugWatcher) {
+        self.hotplug = Some(watcher);
+    }
+
+    /// Set the delay between device scans and reconnection attempts
+    pub fn set_scan_interval(&mut self, interval: Duration) {
+        self.scan_interval = interval;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.devices.iter().any(|device| device.is_connected())
+    }
+
+    pub fn connected_devices(&self) -> Vec<DeviceIdentity> {
+        self.devices.iter()
+            .filter(|device| device.is_connected())
+            .map(|device| device.identity.clone())
+            .collect()
+    }
+
+    /// Discover, connect and reconnect devices.
+    ///
+    /// Scans for devices (rate limited by the scan interval, or right away
+    /// after a hotplug event) and starts a reader thread for each new one.
+    /// Each device keeps its own reconnect state: a read error stops only
+    /// that device's reader, and the device is reopened on a later scan if it
+    /// is still attached. Returns how long the caller may wait before the
+    /// next call, unless a [`MonitorEvent::DevicesChanged`] arrives first.
+    pub fn maintain(&mut self) -> Duration {
+        #[cfg(target_os = "linux")]
+        self.handle_hotplug_events();
+
+        // Reap readers that stopped on their own
+        for device in self.devices.iter_mut() {
+            if device.reader.as_ref().is_some_and(|reader| reader.is_finished()) {
+                if let Some(reader) = device.reader.take() {
+                    reader.stop_and_join();
+                }
+                // Reset connection on error
+                device.last_connection_attempt = Some(Instant::now());
+            }
+        }
+
+        let now = Instant::now();
+        let scan_requested = self.scan_requested_at
+            .is_some_and(|requested_at| now >= requested_at);
+        let scan_due = match self.last_connection_attempt {
+            Some(attempt) => now.duration_since(attempt) >= self.current_scan_interval(),
+            None => true,
+        };
+        if scan_requested || scan_due {
+            self.scan_requested_at = None;
+            self.last_connection_attempt = Some(now);
+            self.scan_and_connect();
+        }
+
+        let next_scan = self.last_connection_attempt.unwrap_or(now) + self.current_scan_interval();
+        let next = match self.scan_requested_at {
+            Some(requested_at) => requested_at.min(next_scan),
+            None => next_scan,
+        };
+        next.saturating_duration_since(Instant::now())
+    }
+
+    /// Stop every reader thread and wait for them to finish
+    pub fn stop(&mut self) {
+        for device in self.devices.drain(..) {
+            if let Some(reader) = device.reader {
+                reader.stop_and_join();
+            }
+        }
+    }
+
+    /// Interval between polling scans; relaxed while hotplug events are
+    /// available and every known device is connected
+    fn current_scan_interval(&self) -> Duration {
+        #[cfg(target_os = "linux")]
+        {
+            let all_connected = self.devices.iter().all(|device| device.is_connected());
+            if self.hotplug.is_some() && all_connected {
+                return HOTPLUG_FALLBACK_SCAN_INTERVAL;
+            }
+        }
+
+        self.scan_interval
+    }
+
+    #[cfg(target_os = "linux")]
+    fn handle_hotplug_events(&mut self) {
+        let events = match &self.hotplug {
+            Some(watcher) => watcher.drain(),
+            None => return,
+        };
+
+        for event in events {
+            match event.action {
+                HotplugAction::Added => {
+                    info!("SVBONY device plugged in at {}", event.devnode);
+                    let scan_at = Instant::now() + HOTPLUG_SETTLE_DELAY;
+                    self.scan_requested_at = Some(match self.scan_requested_at {
+                        Some(requested_at) => requested_at.min(scan_at),
+                        None => scan_at,
+                    });
+                }
+                HotplugAction::Removed => {
+                    self.devices.retain(|device| {
+                        let removed = device.identity.path == event.devnode;
+                        if removed {
+                            info!("SVBONY device {} unplugged", device.identity);
+                        }
+                        !removed
+                    });
+                }
+            }
+        }
+    }
+
+    fn scan_and_connect(&mut self) {
+        let found = match self.connector.scan() {
+            Ok(found) => found,
+            Err(e) => {
+                debug!("Device scan failed: {}", e);
+                return;
+            }
+        };
+
+        // Forget devices that are no longer attached
+        self.devices.retain(|device| {
+            let attached = device.is_connected() || found.contains(&device.identity);
+            if !attached {
+                debug!("Device {} is no longer attached", device.identity);
+            }
+            attached
+        });
+
+        for identity in found {
+            if !self.devices.iter().any(|device| device.identity == identity) {
+                self.devices.push(MonitoredDevice {
+                    identity,
+                    reader: None,
+                    last_connection_attempt: None,
+                });
+            }
+        }
+
+        for device in self.devices.iter_mut().filter(|device| device.reader.is_none()) {
+            let retry_due = match device.last_connection_attempt {
+                Some(attempt) => attempt.elapsed() >= self.scan_interval,
+                None => true,
+            };
+            if !retry_due {
+                continue;
+            }
+
+            device.last_connection_attempt = Some(Instant::now());
+            let reader = self.connector.open(&device.identity)
+                .and_then(|source| DeviceReader::spawn(device.identity.clone(), source, self.events.clone()));
+            match reader {
+                Ok(reader) => {
+                    info!("Connected to SVBONY device {}", device.identity);
+                    device.reader = Some(reader);
+                }
+                Err(e) => {
+                    debug!("Connection to {} failed: {}", device.identity, e);
+                }
+            }
+        }
+
+        if self.devices.is_empty() {
+            debug!("SVBONY device not found (VID: {:04x}, PIDs: {:04x?})",
+                   self.vendor_id, self.product_ids);
+        }
     }
 
     pub fn find_device(&mut self) -> Result<Option<DeviceInfo>> { // Fixed return type
@@ -85,52 +455,84 @@ impl HidMonitor {
     }
 
     pub fn parse_hid_report(&self, data: &[u8]) -> Option<HidReport> {
-        if data.is_empty() {
-            return None;
-        }
+        parse_hid_report(data)
+    }
 
-        let report_id = data[0];
-        let payload = &data[1..];
+    pub fn log_hid_data(&self, data: &[u8], size: usize) {
+        log_hid_data(data, size)
+    }
+}
 
-        Some(HidReport {
-            report_id,
-            payload: payload.to_vec(),
-            timestamp: Instant::now(),
-        })
+fn parse_hid_report(data: &[u8]) -> Option<HidReport> {
+    if data.is_empty() {
+        return None;
     }
 
-    pub fn is_ai_button_press(&self, report: &HidReport) -> bool {
-        // Check for AI button pattern: 04b2000001000100...
-        if report.report_id != 0x04 {
-            return false;
-        }
+    let report_id = data[0];
+    let payload = &data[1..];
 
-        if report.payload.len() < 7 {
-            return false;
+    Some(HidReport {
+        report_id,
+        payload: payload.to_vec(),
+        timestamp: Instant::now(),
+    })
+}
+
+/// Fetch and parse the report descriptor of a freshly opened device
+fn load_descriptor(source: &dyn InputSource, identity: &DeviceIdentity) -> Option<ReportDescriptor> {
+    let descriptor = source.report_descriptor()
+        .and_then(|raw| ReportDescriptor::parse(&raw));
+
+    match descriptor {
+        Ok(descriptor) => {
+            debug!("Input report IDs of {}: {:02x?}", identity, descriptor.report_ids());
+            let buttons: Vec<String> = descriptor.button_usages().iter().map(|usage| usage.to_string()).collect();
+            if !buttons.is_empty() {
+                info!("{} reports buttons: {}", identity, buttons.join(", "));
+            }
+            Some(descriptor)
         }
+        Err(e) => {
+            debug!("No usable report descriptor for {}: {}", identity, e);
+            None
+        }
+    }
+}
 
-        // Check for the specific pattern: b2 00 00 01 00 01 00
-        let expected_payload = [0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00];
-        report.payload[..expected_payload.len()] == expected_payload
+fn decode_usage_edges(descriptor: &ReportDescriptor, tracker: &mut UsageTracker, data: &[u8]) -> Vec<(String, ButtonEdge)> {
+    let decoded = descriptor.decode(data);
+
+    if log::log_enabled!(log::Level::Debug) {
+        let mut usages: Vec<String> = decoded.pressed.iter().map(|usage| format!("{}=1", usage)).collect();
+        usages.extend(decoded.values.iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(usage, value)| format!("{}={}", usage, value)));
+        if !usages.is_empty() {
+            debug!("Decoded: {}", usages.join(", "));
+        }
     }
 
-    pub fn log_hid_data(&self, data: &[u8], size: usize) {
-        if log::log_enabled!(log::Level::Debug) {
-            debug!("HID Data received ({} bytes):", size);
-            
-            // Log data in hex format, 16 bytes per line
-            for (i, chunk) in data[..size].chunks(16).enumerate() {
-                let hex_str: String = chunk.iter()
-                    .map(|b| format!("{:02x}", b))
-                    .collect::<Vec<_>>()
-                    .join(" ");
-                
-                let ascii_str: String = chunk.iter()
-                    .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
-                    .collect();
+    tracker.update(&decoded).into_iter()
+        .map(|(usage, edge)| (usage.to_string(), edge))
+        .collect()
+}
 
-                debug!("{:04x}: {:48} |{}|", i * 16, hex_str, ascii_str);
-            }
+fn log_hid_data(data: &[u8], size: usize) {
+    if log::log_enabled!(log::Level::Debug) {
+        debug!("HID Data received ({} bytes):", size);
+
+        // Log data in hex format, 16 bytes per line
+        for (i, chunk) in data[..size].chunks(16).enumerate() {
+            let hex_str: String = chunk.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let ascii_str: String = chunk.iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+
+            debug!("{:04x}: {:48} |{}|", i * 16, hex_str, ascii_str);
         }
     }
 }
@@ -147,11 +549,23 @@ impl HidReport {
         self.timestamp.elapsed()
     }
 
+    pub fn matches_signature(&self, signature: &ButtonSignature) -> bool {
+        signature.matches(self.report_id, &self.payload)
+    }
+
+    pub fn matches_release_signature(&self, signature: &ButtonSignature) -> bool {
+        signature.matches_release(self.report_id, &self.payload)
+    }
+
+    /// Raw report as read from the device, report id first
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![self.report_id];
+        data.extend_from_slice(&self.payload);
+        data
+    }
+
     pub fn to_hex_string(&self) -> String {
-        let mut hex_data = vec![self.report_id];
-        hex_data.extend_from_slice(&self.payload);
-        
-        hex_data.iter()
+        self.to_bytes().iter()
             .map(|b| format!("{:02x}", b))
             .collect::<Vec<_>>()
             .join("")
@@ -161,11 +575,13 @@ impl HidReport {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
+    use crate::input::tests::test_identity;
+    use crate::input::{ScriptStep, ScriptedConnector, ScriptedDevice, ScriptedSource};
 
     #[test]
     fn test_ai_button_pattern_recognition() {
-        // let monitor = HidMonitor::new(0xe2b7, 0x364d).unwrap();
-        let monitor = HidMonitor::new(0xe2b7, 0x5053).unwrap();
+        let ai = &Config::default_buttons()[0];
         
         // Test valid AI button report
         let valid_report = HidReport {
@@ -174,7 +590,7 @@ mod tests {
             timestamp: Instant::now(),
         };
         
-        assert!(monitor.is_ai_button_press(&valid_report));
+        assert!(valid_report.matches_signature(ai));
         
         // Test invalid report ID
         let invalid_id_report = HidReport {
@@ -183,7 +599,7 @@ mod tests {
             timestamp: Instant::now(),
         };
         
-        assert!(!monitor.is_ai_button_press(&invalid_id_report));
+        assert!(!invalid_id_report.matches_signature(ai));
         
         // Test invalid payload
         let invalid_payload_report = HidReport {
@@ -192,7 +608,7 @@ mod tests {
             timestamp: Instant::now(),
         };
         
-        assert!(!monitor.is_ai_button_press(&invalid_payload_report));
+        assert!(!invalid_payload_report.matches_signature(ai));
     }
 
     #[test]
@@ -236,4 +652,173 @@ mod tests {
         let empty_data = [];
         assert!(monitor.parse_hid_report(&empty_data).is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_signature_mask_and_offset() {
+        let report = HidReport {
+            report_id: 0x04,
+            payload: vec![0xb2, 0x00, 0x00, 0x01, 0x7f],
+            timestamp: Instant::now(),
+        };
+
+        let mut signature = ButtonSignature {
+            name: "test".to_string(),
+            report_id: 0x04,
+            offset: 3,
+            bytes: vec![0x01, 0x0f],
+            mask: vec![0xff, 0x0f],
+            release_bytes: Vec::new(),
+            usage: None,
+            device: None,
+        };
+        assert!(report.matches_signature(&signature));
+
+        // Without the mask the high nibble of the last byte differs
+        signature.mask.clear();
+        assert!(!report.matches_signature(&signature));
+
+        // Signature extends past the end of the payload
+        signature.offset = 4;
+        assert!(!report.matches_signature(&signature));
+    }
+
+    #[test]
+    fn test_signature_hex_config_format() {
+        let json = r#"{"name": "AI", "report_id": 4, "bytes": "b2 00 00 01", "mask": "ff:ff:00:ff"}"#;
+        let signature: ButtonSignature = serde_json::from_str(json).unwrap();
+
+        assert_eq!(signature.offset, 0);
+        assert_eq!(signature.bytes, vec![0xb2, 0x00, 0x00, 0x01]);
+        assert_eq!(signature.mask, vec![0xff, 0xff, 0x00, 0xff]);
+
+        let serialized = serde_json::to_string(&signature).unwrap();
+        assert!(serialized.contains(r#""bytes":"b2 00 00 01""#));
+
+        let invalid = r#"{"name": "AI", "report_id": 4, "bytes": "b2 0"}"#;
+        assert!(serde_json::from_str::<ButtonSignature>(invalid).is_err());
+    }
+
+    /// Keep the monitor maintained until a report arrives or `timeout` expires
+    fn next_report(monitor: &mut HidMonitor, events: &mut UnboundedReceiver<MonitorEvent>,
+                   timeout: Duration) -> Option<DeviceReport> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            monitor.maintain();
+            match events.try_recv() {
+                Ok(MonitorEvent::Report(report)) => return Some(report),
+                Ok(MonitorEvent::DevicesChanged) => {}
+                Err(_) => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        None
+    }
+
+    /// Keep the monitor maintained until `condition` holds
+    fn wait_until(monitor: &mut HidMonitor, condition: impl Fn(&HidMonitor) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            monitor.maintain();
+            if condition(monitor) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    #[test]
+    fn test_reader_reconnects_after_read_error() {
+        let identity = test_identity("scripted/0", 0x5053, "Omni2P", None);
+        let connector = ScriptedConnector::single(identity.clone(), vec![
+            ScriptedSource::new("first", vec![
+                ScriptStep::Report(vec![0x04, 0xb2]),
+                ScriptStep::Disconnect,
+            ]),
+            ScriptedSource::new("second", vec![
+                ScriptStep::Report(vec![0x05, 0x01]),
+            ]),
+        ]);
+        let mut monitor = HidMonitor::with_connector(0xe2b7, vec![0x5053], Box::new(connector));
+        monitor.set_scan_interval(Duration::ZERO);
+        let mut events = monitor.take_events().unwrap();
+        assert!(monitor.take_events().is_none());
+
+        let report = next_report(&mut monitor, &mut events, Duration::from_secs(2)).unwrap();
+        assert_eq!(report.device, identity);
+        assert_eq!(report.report.to_hex_string(), "04b2");
+
+        // The read error stops the reader; the device is reopened
+        let report = next_report(&mut monitor, &mut events, Duration::from_secs(2)).unwrap();
+        assert_eq!(report.report.to_hex_string(), "0501");
+
+        // No more scripted sessions to connect to
+        assert!(next_report(&mut monitor, &mut events, Duration::from_millis(50)).is_none());
+        assert!(monitor.is_connected());
+        monitor.stop();
+        assert!(!monitor.is_connected());
+    }
+
+    #[test]
+    fn test_readers_run_for_every_device() {
+        let m6 = test_identity("scripted/0", 0x364d, "M6", None);
+        let omni = test_identity("scripted/1", 0x5053, "Omni2P", None);
+        let connector = ScriptedConnector::new(vec![
+            ScriptedDevice::new(m6.clone(), vec![
+                ScriptedSource::new("m6", vec![ScriptStep::Disconnect]),
+            ]),
+            ScriptedDevice::new(omni.clone(), vec![
+                ScriptedSource::new("omni", vec![ScriptStep::Idle, ScriptStep::Report(vec![0x04, 0xb2])]),
+            ]),
+        ]);
+        let mut monitor = HidMonitor::with_connector(0xe2b7, vec![0x364d, 0x5053], Box::new(connector));
+        monitor.set_scan_interval(Duration::from_secs(60));
+        let mut events = monitor.take_events().unwrap();
+
+        // Both devices connect on the first call; the M6 then fails on its own
+        let report = next_report(&mut monitor, &mut events, Duration::from_secs(2)).unwrap();
+        assert_eq!(report.device, omni);
+        assert!(wait_until(&mut monitor, |monitor| monitor.connected_devices() == vec![omni.clone()]));
+
+        // The failed device is retried on the next scan, not right away
+        assert!(monitor.maintain() > Duration::from_secs(50));
+        monitor.stop();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_hotplug_events_connect_and_tear_down_immediately() {
+        use crate::hotplug::HotplugEvent;
+        use std::sync::mpsc;
+
+        let identity = test_identity("/dev/hidraw3", 0x364d, "M6", None);
+        let connector = ScriptedConnector::single(identity.clone(), vec![
+            ScriptedSource::new("m6", vec![]),
+            ScriptedSource::new("m6 again", vec![]),
+        ]);
+        let mut monitor = HidMonitor::with_connector(0xe2b7, vec![0x364d], Box::new(connector));
+        let (sender, receiver) = mpsc::channel();
+        monitor.set_hotplug_watcher(HotplugWatcher::from_channel(receiver));
+
+        monitor.maintain();
+        assert!(monitor.is_connected());
+
+        let event = |action| HotplugEvent {
+            action,
+            vendor_id: 0xe2b7,
+            product_id: 0x364d,
+            devnode: "/dev/hidraw3".to_string(),
+        };
+
+        sender.send(event(HotplugAction::Removed)).unwrap();
+        monitor.maintain();
+        assert!(!monitor.is_connected());
+
+        // Without the event the next scan would be 5 seconds away
+        sender.send(event(HotplugAction::Added)).unwrap();
+        assert!(monitor.maintain() <= HOTPLUG_SETTLE_DELAY);
+        std::thread::sleep(HOTPLUG_SETTLE_DELAY);
+        monitor.maintain();
+        assert_eq!(monitor.connected_devices(), vec![identity]);
+        monitor.stop();
+    }
+}
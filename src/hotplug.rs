@@ -0,0 +1,333 @@
+//! Event-driven device hotplug detection on Linux.
+//!
+//! Listens to kernel uevents on a `NETLINK_KOBJECT_UEVENT` socket and reports
+//! hidraw nodes of matching devices as they are added or removed, so the
+//! monitor can connect or tear down immediately instead of waiting for the
+//! next polling scan.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// Multicast group the kernel broadcasts uevents on
+const KERNEL_UEVENT_GROUP: u32 = 1;
+const UEVENT_BUFFER_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugAction {
+    Added,
+    Removed,
+}
+
+/// A hidraw node of a matching device appeared or disappeared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotplugEvent {
+    pub action: HotplugAction,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Device node such as "/dev/hidraw3", which is also the hidapi path
+    pub devnode: String,
+}
+
+/// Background thread that forwards matching hotplug events.
+/// The thread is stopped when the watcher is dropped.
+#[derive(Debug)]
+pub struct HotplugWatcher {
+    events: Receiver<HotplugEvent>,
+    // Writing end of the pipe that wakes the thread up for shutdown
+    stop: Option<OwnedFd>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotplugWatcher {
+    /// Start watching for hidraw nodes of `vendor_id` devices with one of
+    /// `product_ids`. `waker` is called after each queued event.
+    pub fn start(vendor_id: u16, product_ids: Vec<u16>, waker: Box<dyn Fn() + Send>) -> Result<Self> {
+        let socket = open_uevent_socket()?;
+        let (stop_read, stop_write) = create_pipe()?;
+        let (sender, events) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("hotplug".to_string())
+            .spawn(move || {
+                watch_uevents(socket, stop_read, sender, waker, vendor_id, &product_ids);
+            })
+            .context("Failed to spawn hotplug thread")?;
+
+        info!("Listening for device hotplug events");
+        Ok(HotplugWatcher {
+            events,
+            stop: Some(stop_write),
+            thread: Some(thread),
+        })
+    }
+
+    /// Watcher fed by an existing channel instead of a netlink socket
+    #[cfg(test)]
+    pub fn from_channel(events: Receiver<HotplugEvent>) -> Self {
+        HotplugWatcher {
+            events,
+            stop: None,
+            thread: None,
+        }
+    }
+
+    /// Take every event received since the last call
+    pub fn drain(&self) -> Vec<HotplugEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        // Closing the pipe wakes the thread up and makes it exit
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn open_uevent_socket() -> Result<OwnedFd> {
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        );
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to create uevent socket");
+        }
+        let socket = OwnedFd::from_raw_fd(fd);
+
+        let mut address: libc::sockaddr_nl = std::mem::zeroed();
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = KERNEL_UEVENT_GROUP;
+
+        let result = libc::bind(
+            socket.as_raw_fd(),
+            &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        );
+        if result < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to bind uevent socket");
+        }
+
+        Ok(socket)
+    }
+}
+
+fn create_pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) < 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to create pipe");
+        }
+        Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])))
+    }
+}
+
+fn watch_uevents(
+    socket: OwnedFd,
+    stop: OwnedFd,
+    sender: Sender<HotplugEvent>,
+    waker: Box<dyn Fn() + Send>,
+    vendor_id: u16,
+    product_ids: &[u16],
+) {
+    let mut buffer = vec![0u8; UEVENT_BUFFER_SIZE];
+
+    loop {
+        let mut fds = [
+            libc::pollfd { fd: socket.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: stop.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+
+        // Block without timeout: the thread only wakes up for uevents or shutdown
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if ready < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            warn!("Hotplug watcher stopped: {}", error);
+            return;
+        }
+
+        if fds[1].revents != 0 {
+            debug!("Hotplug watcher shutting down");
+            return;
+        }
+
+        if fds[0].revents & libc::POLLIN == 0 {
+            continue;
+        }
+
+        let size = unsafe {
+            libc::recv(socket.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+        };
+        if size <= 0 {
+            continue;
+        }
+
+        let event = match parse_uevent(&buffer[..size as usize]) {
+            Some(event) => event,
+            None => continue,
+        };
+
+        if event.vendor_id != vendor_id || !product_ids.contains(&event.product_id) {
+            continue;
+        }
+
+        debug!("Hotplug event: {:?}", event);
+        if sender.send(event).is_err() {
+            // Monitor is gone
+            return;
+        }
+        waker();
+    }
+}
+
+/// Parse a kernel uevent ("add@/devices/...\0ACTION=add\0KEY=value\0...")
+/// for a hidraw node, returning `None` for any other uevent
+pub fn parse_uevent(message: &[u8]) -> Option<HotplugEvent> {
+    let mut action = None;
+    let mut devpath = None;
+    let mut subsystem = None;
+    let mut devname = None;
+
+    // The first field is the "action@devpath" summary, the rest are KEY=value pairs
+    for field in message.split(|&b| b == 0).skip(1) {
+        let field = std::str::from_utf8(field).ok()?;
+        match field.split_once('=') {
+            Some(("ACTION", value)) => action = Some(value),
+            Some(("DEVPATH", value)) => devpath = Some(value),
+            Some(("SUBSYSTEM", value)) => subsystem = Some(value),
+            Some(("DEVNAME", value)) => devname = Some(value),
+            _ => {}
+        }
+    }
+
+    if subsystem != Some("hidraw") {
+        return None;
+    }
+
+    let action = match action? {
+        "add" => HotplugAction::Added,
+        "remove" => HotplugAction::Removed,
+        _ => return None,
+    };
+
+    let (vendor_id, product_id) = parse_hid_id(devpath?)?;
+    let devname = devname?;
+    let devnode = if devname.starts_with('/') {
+        devname.to_string()
+    } else {
+        format!("/dev/{}", devname)
+    };
+
+    Some(HotplugEvent {
+        action,
+        vendor_id,
+        product_id,
+        devnode,
+    })
+}
+
+/// Extract VID and PID from the HID device directory in a sysfs path, e.g.
+/// ".../0003:E2B7:364D.0005/hidraw/hidraw3" (bus:vendor:product.instance)
+fn parse_hid_id(devpath: &str) -> Option<(u16, u16)> {
+    devpath.split('/').rev().find_map(|segment| {
+        let (ids, instance) = segment.split_once('.')?;
+        let mut parts = ids.split(':');
+        let (bus, vendor, product) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || instance.is_empty()
+            || bus.len() != 4 || vendor.len() != 4 || product.len() != 4 {
+            return None;
+        }
+
+        Some((u16::from_str_radix(vendor, 16).ok()?, u16::from_str_radix(product, 16).ok()?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uevent(fields: &[&str]) -> Vec<u8> {
+        let mut message = Vec::new();
+        for field in fields {
+            message.extend_from_slice(field.as_bytes());
+            message.push(0);
+        }
+        message
+    }
+
+    const HIDRAW_DEVPATH: &str =
+        "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.3/0003:E2B7:364D.0005/hidraw/hidraw3";
+
+    #[test]
+    fn test_parse_hidraw_add_and_remove() {
+        let add = uevent(&[
+            &format!("add@{}", HIDRAW_DEVPATH),
+            "ACTION=add",
+            &format!("DEVPATH={}", HIDRAW_DEVPATH),
+            "SUBSYSTEM=hidraw",
+            "MAJOR=240",
+            "MINOR=3",
+            "DEVNAME=hidraw3",
+            "SEQNUM=4711",
+        ]);
+
+        assert_eq!(parse_uevent(&add), Some(HotplugEvent {
+            action: HotplugAction::Added,
+            vendor_id: 0xe2b7,
+            product_id: 0x364d,
+            devnode: "/dev/hidraw3".to_string(),
+        }));
+
+        let remove = uevent(&[
+            &format!("remove@{}", HIDRAW_DEVPATH),
+            "ACTION=remove",
+            &format!("DEVPATH={}", HIDRAW_DEVPATH),
+            "SUBSYSTEM=hidraw",
+            "DEVNAME=hidraw3",
+        ]);
+        assert_eq!(parse_uevent(&remove).unwrap().action, HotplugAction::Removed);
+    }
+
+    #[test]
+    fn test_ignore_other_uevents() {
+        // The parent HID device, not its hidraw node
+        let hid = uevent(&[
+            "add@/devices/pci0000:00/usb1/1-2/1-2:1.3/0003:E2B7:364D.0005",
+            "ACTION=add",
+            "DEVPATH=/devices/pci0000:00/usb1/1-2/1-2:1.3/0003:E2B7:364D.0005",
+            "SUBSYSTEM=hid",
+            "HID_ID=0003:0000E2B7:0000364D",
+        ]);
+        assert_eq!(parse_uevent(&hid), None);
+
+        let change = uevent(&[
+            &format!("change@{}", HIDRAW_DEVPATH),
+            "ACTION=change",
+            &format!("DEVPATH={}", HIDRAW_DEVPATH),
+            "SUBSYSTEM=hidraw",
+            "DEVNAME=hidraw3",
+        ]);
+        assert_eq!(parse_uevent(&change), None);
+
+        // udevd re-broadcasts use a binary header and are not parsed
+        assert_eq!(parse_uevent(b"libudev\0\xfe\xed\xca\xfe"), None);
+    }
+
+    #[test]
+    fn test_parse_hid_id() {
+        assert_eq!(parse_hid_id(HIDRAW_DEVPATH), Some((0xe2b7, 0x364d)));
+        assert_eq!(parse_hid_id("/devices/virtual/misc/uhid/0005:E2B7:5053.000A/hidraw/hidraw0"),
+                   Some((0xe2b7, 0x5053)));
+        assert_eq!(parse_hid_id("/devices/pci0000:00/0000:00:14.0/usb1"), None);
+    }
+}
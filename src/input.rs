@@ -0,0 +1,372 @@
+use anyhow::{Context, Result};
+use hidapi::{HidApi, HidDevice};
+use log::debug;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A source of raw HID input reports.
+///
+/// Implemented by the real `hidapi` device and by [`ScriptedSource`], so the
+/// press-to-action path can run without SVBONY hardware attached.
+pub trait InputSource: Send {
+    /// Read one report into `buf`, waiting at most `timeout_ms` milliseconds.
+    /// Returns `Ok(0)` when no report arrived in time.
+    fn read_report(&mut self, buf: &mut [u8], timeout_ms: i32) -> Result<usize>;
+
+    /// Human readable identity of the source, used in log messages
+    fn description(&self) -> String;
+
+    /// Raw HID report descriptor, describing the layout of the reports
+    fn report_descriptor(&self) -> Result<Vec<u8>> {
+        Err(anyhow::anyhow!("Report descriptor not available"))
+    }
+}
+
+/// Identity of one attached device, as reported by a [`DeviceConnector`] scan
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceIdentity {
+    /// Platform device path; unique per attached device interface
+    pub path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Model name such as "M6", used to scope actions to a device
+    pub model: String,
+    pub serial_number: Option<String>,
+}
+
+impl fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (PID: {:04x}", self.model, self.product_id)?;
+        if let Some(serial) = &self.serial_number {
+            write!(f, ", serial: {}", serial)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Finds and opens input sources; used by `HidMonitor` to discover devices
+/// and to (re)connect each of them
+pub trait DeviceConnector: Send + fmt::Debug {
+    /// List every matching device currently attached
+    fn scan(&mut self) -> Result<Vec<DeviceIdentity>>;
+
+    /// Open a device returned by `scan`
+    fn open(&mut self, device: &DeviceIdentity) -> Result<Box<dyn InputSource>>;
+}
+
+impl InputSource for HidDevice {
+    fn read_report(&mut self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        self.read_timeout(buf, timeout_ms)
+            .map_err(|e| anyhow::anyhow!("HID read error: {}", e))
+    }
+
+    fn description(&self) -> String {
+        match self.get_product_string() {
+            Ok(Some(product)) => product,
+            _ => "HID device".to_string(),
+        }
+    }
+
+    fn report_descriptor(&self) -> Result<Vec<u8>> {
+        let mut descriptor = vec![0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+        let size = self.get_report_descriptor(&mut descriptor)
+            .map_err(|e| anyhow::anyhow!("Failed to read report descriptor: {}", e))?;
+        descriptor.truncate(size);
+        Ok(descriptor)
+    }
+}
+
+/// Connects to real devices through `hidapi`
+#[derive(Debug, Clone)]
+pub struct HidApiConnector {
+    vendor_id: u16,
+    /// Supported product IDs and their model names
+    models: Vec<(u16, String)>,
+}
+
+impl HidApiConnector {
+    pub fn new(vendor_id: u16, models: &[(u16, &str)]) -> Self {
+        HidApiConnector {
+            vendor_id,
+            models: models.iter()
+                .map(|(product_id, model)| (*product_id, model.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl DeviceConnector for HidApiConnector {
+    fn scan(&mut self) -> Result<Vec<DeviceIdentity>> {
+        let api = HidApi::new()
+            .context("Failed to initialize HID API")?;
+
+        let devices = api.device_list()
+            .filter(|device_info| device_info.vendor_id() == self.vendor_id)
+            .filter_map(|device_info| {
+                let (product_id, model) = self.models.iter()
+                    .find(|(product_id, _)| *product_id == device_info.product_id())?;
+
+                Some(DeviceIdentity {
+                    path: device_info.path().to_string_lossy().into_owned(),
+                    vendor_id: self.vendor_id,
+                    product_id: *product_id,
+                    model: model.clone(),
+                    serial_number: device_info.serial_number()
+                        .filter(|serial| !serial.is_empty())
+                        .map(|serial| serial.to_string()),
+                })
+            })
+            .collect();
+
+        Ok(devices)
+    }
+
+    fn open(&mut self, device: &DeviceIdentity) -> Result<Box<dyn InputSource>> {
+        let api = HidApi::new()
+            .context("Failed to initialize HID API")?;
+
+        let path = CString::new(device.path.clone())
+            .context("Invalid device path")?;
+        let hid_device = api.open_path(&path)
+            .with_context(|| format!("Failed to open SVBONY device {}", device))?;
+
+        debug!("Opened {} at {}", hid_device.description(), device.path);
+        Ok(Box::new(hid_device))
+    }
+}
+
+// Longest time a scripted read waits when there is no data
+const SCRIPTED_IDLE_MS: i32 = 5;
+
+/// One step of a [`ScriptedSource`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+    /// Deliver this raw report (report id first)
+    Report(Vec<u8>),
+    /// Return no data for one read
+    #[cfg_attr(not(test), allow(dead_code))]
+    Idle,
+    /// Return no data until this much time has passed
+    Wait(Duration),
+    /// Fail the read, as a real device does when it is unplugged
+    #[cfg_attr(not(test), allow(dead_code))]
+    Disconnect,
+}
+
+/// In-memory input source that plays back a fixed list of steps, used by
+/// tests and to replay captured reports.
+/// Once the script is exhausted every read returns no data. Reads without
+/// data wait at most a few milliseconds, whatever the requested timeout, to
+/// keep tests fast.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedSource {
+    name: String,
+    steps: VecDeque<ScriptStep>,
+    // End of the `Wait` step being played
+    wait_until: Option<Instant>,
+    descriptor: Option<Vec<u8>>,
+}
+
+impl ScriptedSource {
+    pub fn new(name: &str, steps: Vec<ScriptStep>) -> Self {
+        ScriptedSource {
+            name: name.to_string(),
+            steps: steps.into(),
+            wait_until: None,
+            descriptor: None,
+        }
+    }
+
+    /// Report `descriptor` as the HID report descriptor of this source
+    #[cfg(test)]
+    pub fn with_descriptor(mut self, descriptor: &[u8]) -> Self {
+        self.descriptor = Some(descriptor.to_vec());
+        self
+    }
+}
+
+impl InputSource for ScriptedSource {
+    fn read_report(&mut self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        if self.wait_until.is_none() {
+            if let Some(ScriptStep::Wait(duration)) = self.steps.front() {
+                self.wait_until = Some(Instant::now() + *duration);
+                self.steps.pop_front();
+            }
+        }
+
+        if let Some(wait_until) = self.wait_until {
+            // Never block longer than the caller asked for; a negative
+            // timeout waits without limit
+            let remaining = wait_until.saturating_duration_since(Instant::now());
+            let waited = if timeout_ms < 0 {
+                remaining
+            } else {
+                remaining.min(Duration::from_millis(timeout_ms as u64))
+            };
+            std::thread::sleep(waited);
+            if Instant::now() >= wait_until {
+                self.wait_until = None;
+            }
+            return Ok(0);
+        }
+
+        match self.steps.pop_front() {
+            Some(ScriptStep::Report(data)) => {
+                let size = data.len().min(buf.len());
+                buf[..size].copy_from_slice(&data[..size]);
+                Ok(size)
+            }
+            Some(ScriptStep::Idle) | Some(ScriptStep::Wait(_)) | None => {
+                let idle_ms = if timeout_ms < 0 { SCRIPTED_IDLE_MS } else { timeout_ms.min(SCRIPTED_IDLE_MS) };
+                std::thread::sleep(Duration::from_millis(idle_ms as u64));
+                Ok(0)
+            }
+            Some(ScriptStep::Disconnect) => {
+                Err(anyhow::anyhow!("Scripted device '{}' disconnected", self.name))
+            }
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("scripted device '{}'", self.name)
+    }
+
+    fn report_descriptor(&self) -> Result<Vec<u8>> {
+        self.descriptor.clone()
+            .ok_or_else(|| anyhow::anyhow!("Scripted device '{}' has no report descriptor", self.name))
+    }
+}
+
+/// Scripted devices for [`ScriptedConnector`]: each device hands out one
+/// [`ScriptedSource`] per connection, in order
+#[derive(Debug, Clone)]
+pub struct ScriptedDevice {
+    pub identity: DeviceIdentity,
+    sessions: VecDeque<ScriptedSource>,
+}
+
+impl ScriptedDevice {
+    pub fn new(identity: DeviceIdentity, sessions: Vec<ScriptedSource>) -> Self {
+        ScriptedDevice {
+            identity,
+            sessions: sessions.into(),
+        }
+    }
+}
+
+/// Connector over a fixed set of scripted devices. A device disappears from
+/// scans once all of its sessions have been used.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedConnector {
+    devices: Vec<ScriptedDevice>,
+}
+
+impl ScriptedConnector {
+    pub fn new(devices: Vec<ScriptedDevice>) -> Self {
+        ScriptedConnector { devices }
+    }
+
+    /// Connector with one device that plays back `sessions`
+    #[cfg(test)]
+    pub fn single(identity: DeviceIdentity, sessions: Vec<ScriptedSource>) -> Self {
+        Self::new(vec![ScriptedDevice::new(identity, sessions)])
+    }
+}
+
+impl DeviceConnector for ScriptedConnector {
+    fn scan(&mut self) -> Result<Vec<DeviceIdentity>> {
+        Ok(self.devices.iter()
+            .filter(|device| !device.sessions.is_empty())
+            .map(|device| device.identity.clone())
+            .collect())
+    }
+
+    fn open(&mut self, identity: &DeviceIdentity) -> Result<Box<dyn InputSource>> {
+        let device = self.devices.iter_mut()
+            .find(|device| device.identity.path == identity.path)
+            .context("Unknown scripted device")?;
+
+        match device.sessions.pop_front() {
+            Some(source) => Ok(Box::new(source)),
+            None => Err(anyhow::anyhow!("Scripted device {} is not attached", identity)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_source_plays_steps_in_order() {
+        let mut source = ScriptedSource::new("test", vec![
+            ScriptStep::Report(vec![0x04, 0xb2, 0x00]),
+            ScriptStep::Idle,
+            ScriptStep::Disconnect,
+        ]);
+        let mut buffer = [0u8; 2];
+
+        // Reports larger than the buffer are truncated
+        assert_eq!(source.read_report(&mut buffer, 10).unwrap(), 2);
+        assert_eq!(buffer, [0x04, 0xb2]);
+
+        assert_eq!(source.read_report(&mut buffer, 10).unwrap(), 0);
+        assert!(source.read_report(&mut buffer, 10).is_err());
+
+        // Exhausted scripts stay idle
+        assert_eq!(source.read_report(&mut buffer, 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_scripted_wait_is_split_by_read_timeout() {
+        let mut source = ScriptedSource::new("test", vec![
+            ScriptStep::Wait(Duration::from_millis(25)),
+            ScriptStep::Report(vec![0x04]),
+        ]);
+        let mut buffer = [0u8; 8];
+
+        let started = Instant::now();
+        let mut reads = 0;
+        while source.read_report(&mut buffer, 10).unwrap() == 0 {
+            reads += 1;
+        }
+        assert_eq!(buffer[0], 0x04);
+        assert!(started.elapsed() >= Duration::from_millis(25));
+        // Each read returned once its timeout expired
+        assert!(reads >= 3);
+    }
+
+    pub fn test_identity(path: &str, product_id: u16, model: &str, serial: Option<&str>) -> DeviceIdentity {
+        DeviceIdentity {
+            path: path.to_string(),
+            vendor_id: 0xe2b7,
+            product_id,
+            model: model.to_string(),
+            serial_number: serial.map(|serial| serial.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_scripted_connector_hands_out_sessions() {
+        let identity = test_identity("scripted/0", 0x5053, "Omni2P", None);
+        let mut connector = ScriptedConnector::single(identity.clone(), vec![
+            ScriptedSource::new("first", vec![]),
+        ]);
+
+        assert_eq!(connector.scan().unwrap(), vec![identity.clone()]);
+        let source = connector.open(&identity).unwrap();
+        assert_eq!(source.description(), "scripted device 'first'");
+
+        // All sessions used: the device is gone
+        assert!(connector.scan().unwrap().is_empty());
+        assert!(connector.open(&identity).is_err());
+    }
+
+    #[test]
+    fn test_device_identity_display() {
+        let identity = test_identity("scripted/0", 0x364d, "M6", Some("A1B2"));
+        assert_eq!(identity.to_string(), "M6 (PID: 364d, serial: A1B2)");
+    }
+}
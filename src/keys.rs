@@ -0,0 +1,490 @@
+//! Key sequence parsing for the SendKeys action.
+//!
+//! A key sequence is a whitespace separated list of:
+//! - chords such as `ctrl+shift+m` or `alt+{f4}`: modifiers are held while
+//!   the last key is tapped
+//! - named keys in braces such as `{enter}`, `{tab}` or `{f5}`
+//! - delays such as `{delay 500}` (milliseconds)
+//! - literal text, typed as is: `hello` or `"hello world"`; whitespace
+//!   between tokens is never typed, use quotes or `{space}`
+//!
+//! The parser produces a list of key down/up events that the platform
+//! backends replay.
+
+use anyhow::Result;
+use std::time::Duration;
+
+// Longest delay accepted between keys
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Ctrl,
+    Shift,
+    Alt,
+    Super,
+    Enter,
+    Tab,
+    Escape,
+    Backspace,
+    Delete,
+    Insert,
+    Space,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+    /// A character, typed with whatever modifiers the layout needs
+    Char(char),
+}
+
+impl Key {
+    /// Look up a key by name, ignoring case
+    pub fn from_name(name: &str) -> Option<Key> {
+        let name = name.to_ascii_lowercase();
+        let key = match name.as_str() {
+            "ctrl" | "control" => Key::Ctrl,
+            "shift" => Key::Shift,
+            "alt" | "option" => Key::Alt,
+            "super" | "win" | "meta" | "cmd" => Key::Super,
+            "enter" | "return" => Key::Enter,
+            "tab" => Key::Tab,
+            "esc" | "escape" => Key::Escape,
+            "backspace" => Key::Backspace,
+            "delete" | "del" => Key::Delete,
+            "insert" | "ins" => Key::Insert,
+            "space" => Key::Space,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pageup" | "pgup" => Key::PageUp,
+            "pagedown" | "pgdn" => Key::PageDown,
+            _ => {
+                let number: u8 = name.strip_prefix('f')?.parse().ok()?;
+                if !(1..=12).contains(&number) {
+                    return None;
+                }
+                Key::F(number)
+            }
+        };
+        Some(key)
+    }
+
+    pub fn is_modifier(&self) -> bool {
+        matches!(self, Key::Ctrl | Key::Shift | Key::Alt | Key::Super)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(Key),
+    Up(Key),
+    Delay(Duration),
+}
+
+/// Parse a key sequence into key events
+pub fn parse_keys(spec: &str) -> Result<Vec<KeyEvent>> {
+    let mut events = Vec::new();
+    let mut chars = spec.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        match c {
+            '{' => {
+                let end = spec[start..].find('}')
+                    .ok_or_else(|| anyhow::anyhow!("Unterminated '{{' at position {}", start))?;
+                let name = spec[start + 1..start + end].trim();
+                parse_braced(name, &mut events)?;
+                while chars.next_if(|&(index, _)| index <= start + end).is_some() {}
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                let mut terminated = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            terminated = true;
+                            break;
+                        }
+                        '\\' => match chars.next() {
+                            Some((_, escaped)) => text.push(escaped),
+                            None => break,
+                        },
+                        c => text.push(c),
+                    }
+                }
+                if !terminated {
+                    return Err(anyhow::anyhow!("Unterminated quote at position {}", start));
+                }
+                push_text(&text, &mut events);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| !c.is_whitespace()) {
+                    word.push(c);
+                }
+                parse_word(&word, &mut events)?;
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+fn parse_braced(name: &str, events: &mut Vec<KeyEvent>) -> Result<()> {
+    let mut parts = name.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(word), Some(milliseconds), None) if word.eq_ignore_ascii_case("delay") => {
+            let milliseconds: u64 = milliseconds.parse()
+                .map_err(|_| anyhow::anyhow!("Invalid delay '{}'", milliseconds))?;
+            let delay = Duration::from_millis(milliseconds);
+            if delay > MAX_DELAY {
+                return Err(anyhow::anyhow!("Delay of {} ms is longer than {} s", milliseconds, MAX_DELAY.as_secs()));
+            }
+            events.push(KeyEvent::Delay(delay));
+        }
+        (Some(name), None, None) => {
+            let key = Key::from_name(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown key '{{{}}}'", name))?;
+            tap(key, events);
+        }
+        _ => return Err(anyhow::anyhow!("Invalid key '{{{}}}'", name)),
+    }
+    Ok(())
+}
+
+/// A bare word is a chord when it starts with a modifier, otherwise text
+fn parse_word(word: &str, events: &mut Vec<KeyEvent>) -> Result<()> {
+    let (modifiers, key) = match word.strip_suffix("++") {
+        // "ctrl++" holds ctrl and taps the plus key
+        Some(modifiers) => (modifiers, "+"),
+        None => match word.rsplit_once('+') {
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", word),
+        },
+    };
+
+    let is_chord = !modifiers.is_empty()
+        && modifiers.split('+').next().and_then(Key::from_name).is_some_and(|key| key.is_modifier());
+    if !is_chord {
+        push_text(word, events);
+        return Ok(());
+    }
+
+    let modifiers = modifiers.split('+')
+        .map(|name| match Key::from_name(name) {
+            Some(key) if key.is_modifier() => Ok(key),
+            _ => Err(anyhow::anyhow!("'{}' in '{}' is not a modifier", name, word)),
+        })
+        .collect::<Result<Vec<Key>>>()?;
+
+    let key_name = key.trim_start_matches('{').trim_end_matches('}');
+    let mut key_chars = key_name.chars();
+    let key = match (key_chars.next(), key_chars.next()) {
+        // Chords name the key, so letters are not shifted
+        (Some(c), None) => Key::Char(c.to_ascii_lowercase()),
+        _ => Key::from_name(key_name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown key '{}' in '{}'", key_name, word))?,
+    };
+
+    events.extend(modifiers.iter().map(|modifier| KeyEvent::Down(*modifier)));
+    tap(key, events);
+    events.extend(modifiers.iter().rev().map(|modifier| KeyEvent::Up(*modifier)));
+    Ok(())
+}
+
+fn push_text(text: &str, events: &mut Vec<KeyEvent>) {
+    for c in text.chars() {
+        tap(Key::Char(c), events);
+    }
+}
+
+fn tap(key: Key, events: &mut Vec<KeyEvent>) {
+    events.push(KeyEvent::Down(key));
+    events.push(KeyEvent::Up(key));
+}
+
+/// Split events into key batches at each delay
+pub fn split_at_delays(events: &[KeyEvent]) -> Vec<(Vec<KeyEvent>, Option<Duration>)> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+
+    for event in events {
+        match event {
+            KeyEvent::Delay(delay) => batches.push((std::mem::take(&mut batch), Some(*delay))),
+            event => batch.push(*event),
+        }
+    }
+    if !batch.is_empty() {
+        batches.push((batch, None));
+    }
+
+    batches
+}
+
+/// Arguments for `xdotool` (X11) that replay `events`, which must not
+/// contain delays
+pub fn xdotool_args(events: &[KeyEvent]) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for event in events {
+        let (command, key) = match event {
+            KeyEvent::Down(key) => ("keydown", key),
+            KeyEvent::Up(key) => ("keyup", key),
+            KeyEvent::Delay(_) => continue,
+        };
+        args.push(command.to_string());
+        args.push(x11_keysym(*key));
+    }
+
+    args
+}
+
+fn x11_keysym(key: Key) -> String {
+    let name = match key {
+        Key::Ctrl => "ctrl",
+        Key::Shift => "shift",
+        Key::Alt => "alt",
+        Key::Super => "super",
+        Key::Enter => "Return",
+        Key::Tab => "Tab",
+        Key::Escape => "Escape",
+        Key::Backspace => "BackSpace",
+        Key::Delete => "Delete",
+        Key::Insert => "Insert",
+        Key::Space => "space",
+        Key::Up => "Up",
+        Key::Down => "Down",
+        Key::Left => "Left",
+        Key::Right => "Right",
+        Key::Home => "Home",
+        Key::End => "End",
+        Key::PageUp => "Prior",
+        Key::PageDown => "Next",
+        Key::F(number) => return format!("F{}", number),
+        Key::Char(c) if c.is_ascii_alphanumeric() => return c.to_string(),
+        Key::Char(c) => match c {
+            ' ' => "space",
+            '!' => "exclam",
+            '"' => "quotedbl",
+            '#' => "numbersign",
+            '$' => "dollar",
+            '%' => "percent",
+            '&' => "ampersand",
+            '\'' => "apostrophe",
+            '(' => "parenleft",
+            ')' => "parenright",
+            '*' => "asterisk",
+            '+' => "plus",
+            ',' => "comma",
+            '-' => "minus",
+            '.' => "period",
+            '/' => "slash",
+            ':' => "colon",
+            ';' => "semicolon",
+            '<' => "less",
+            '=' => "equal",
+            '>' => "greater",
+            '?' => "question",
+            '@' => "at",
+            '[' => "bracketleft",
+            '\\' => "backslash",
+            ']' => "bracketright",
+            '^' => "asciicircum",
+            '_' => "underscore",
+            '`' => "grave",
+            '{' => "braceleft",
+            '|' => "bar",
+            '}' => "braceright",
+            '~' => "asciitilde",
+            '\n' => "Return",
+            '\t' => "Tab",
+            // Unicode keysyms cover everything else
+            c => return format!("U{:04X}", c as u32),
+        },
+    };
+    name.to_string()
+}
+
+/// Arguments for `ydotool key` (uinput, works on Wayland) that replay
+/// `events`, which must not contain delays. Characters are typed on a US
+/// layout; characters it cannot produce are an error.
+pub fn ydotool_args(events: &[KeyEvent]) -> Result<Vec<String>> {
+    let mut args = vec!["key".to_string()];
+
+    for event in events {
+        let (key, down) = match event {
+            KeyEvent::Down(key) => (*key, true),
+            KeyEvent::Up(key) => (*key, false),
+            KeyEvent::Delay(_) => continue,
+        };
+        let (code, shifted) = evdev_code(key)
+            .ok_or_else(|| anyhow::anyhow!("Cannot type {:?} on a US keyboard layout", key))?;
+
+        let state = if down { 1 } else { 0 };
+        match (shifted, down) {
+            (true, true) => args.extend([format!("{}:1", EVDEV_LEFT_SHIFT), format!("{}:{}", code, state)]),
+            (true, false) => args.extend([format!("{}:{}", code, state), format!("{}:0", EVDEV_LEFT_SHIFT)]),
+            (false, _) => args.push(format!("{}:{}", code, state)),
+        }
+    }
+
+    Ok(args)
+}
+
+const EVDEV_LEFT_SHIFT: u16 = 42;
+
+/// Linux input event code of a key, and whether shift must be held
+fn evdev_code(key: Key) -> Option<(u16, bool)> {
+    let code = match key {
+        Key::Ctrl => 29,
+        Key::Shift => EVDEV_LEFT_SHIFT,
+        Key::Alt => 56,
+        Key::Super => 125,
+        Key::Enter => 28,
+        Key::Tab => 15,
+        Key::Escape => 1,
+        Key::Backspace => 14,
+        Key::Delete => 111,
+        Key::Insert => 110,
+        Key::Space => 57,
+        Key::Up => 103,
+        Key::Down => 108,
+        Key::Left => 105,
+        Key::Right => 106,
+        Key::Home => 102,
+        Key::End => 107,
+        Key::PageUp => 104,
+        Key::PageDown => 109,
+        Key::F(number @ 1..=10) => 58 + number as u16,
+        Key::F(11) => 87,
+        Key::F(12) => 88,
+        Key::F(_) => return None,
+        Key::Char(c) => return char_code(c),
+    };
+    Some((code, false))
+}
+
+fn char_code(c: char) -> Option<(u16, bool)> {
+    const LETTERS: &[u16; 26] = &[
+        30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45, 21, 44,
+    ];
+    // Unshifted and shifted characters of the US layout, by key
+    const SYMBOLS: &[(char, char, u16)] = &[
+        ('1', '!', 2), ('2', '@', 3), ('3', '#', 4), ('4', '$', 5), ('5', '%', 6),
+        ('6', '^', 7), ('7', '&', 8), ('8', '*', 9), ('9', '(', 10), ('0', ')', 11),
+        ('-', '_', 12), ('=', '+', 13), ('[', '{', 26), (']', '}', 27), (';', ':', 39),
+        ('\'', '"', 40), ('`', '~', 41), ('\\', '|', 43), (',', '<', 51), ('.', '>', 52),
+        ('/', '?', 53), (' ', ' ', 57), ('\n', '\n', 28), ('\t', '\t', 15),
+    ];
+
+    if c.is_ascii_lowercase() {
+        return Some((LETTERS[(c as u8 - b'a') as usize], false));
+    }
+    if c.is_ascii_uppercase() {
+        return Some((LETTERS[(c as u8 - b'A') as usize], true));
+    }
+    SYMBOLS.iter().find_map(|&(plain, shifted, code)| {
+        if c == plain {
+            Some((code, false))
+        } else if c == shifted {
+            Some((code, true))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyEvent::{Delay, Down, Up};
+
+    #[test]
+    fn test_parse_chord() {
+        assert_eq!(parse_keys("ctrl+shift+M").unwrap(), vec![
+            Down(Key::Ctrl), Down(Key::Shift), Down(Key::Char('m')), Up(Key::Char('m')), Up(Key::Shift), Up(Key::Ctrl),
+        ]);
+        assert_eq!(parse_keys("alt+{F4}").unwrap(), parse_keys("Alt+f4").unwrap());
+        assert_eq!(parse_keys("ctrl++").unwrap(), vec![
+            Down(Key::Ctrl), Down(Key::Char('+')), Up(Key::Char('+')), Up(Key::Ctrl),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_named_keys_text_and_delays() {
+        assert_eq!(parse_keys("hi {enter} {delay 250} \"a b\"").unwrap(), vec![
+            Down(Key::Char('h')), Up(Key::Char('h')),
+            Down(Key::Char('i')), Up(Key::Char('i')),
+            Down(Key::Enter), Up(Key::Enter),
+            Delay(Duration::from_millis(250)),
+            Down(Key::Char('a')), Up(Key::Char('a')),
+            Down(Key::Char(' ')), Up(Key::Char(' ')),
+            Down(Key::Char('b')), Up(Key::Char('b')),
+        ]);
+
+        // Text keeps its case; escapes in quotes
+        assert_eq!(parse_keys(r#""Q\"""#).unwrap(), vec![
+            Down(Key::Char('Q')), Up(Key::Char('Q')), Down(Key::Char('"')), Up(Key::Char('"')),
+        ]);
+
+        // Words that don't start with a modifier are text
+        assert_eq!(parse_keys("c+d").unwrap().len(), 6);
+        assert!(parse_keys("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reject_invalid_sequences() {
+        assert!(parse_keys("{enter").is_err());
+        assert!(parse_keys("{nosuchkey}").is_err());
+        assert!(parse_keys("{f13}").is_err());
+        assert!(parse_keys("{delay soon}").is_err());
+        assert!(parse_keys("{delay 600000}").is_err());
+        assert!(parse_keys("\"open").is_err());
+        assert!(parse_keys("ctrl+nosuchkey").is_err());
+        assert!(parse_keys("ctrl+a+b").is_err());
+    }
+
+    #[test]
+    fn test_split_at_delays() {
+        let events = parse_keys("a {delay 100} b").unwrap();
+        let batches = split_at_delays(&events);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0], (vec![Down(Key::Char('a')), Up(Key::Char('a'))], Some(Duration::from_millis(100))));
+        assert_eq!(batches[1].1, None);
+    }
+
+    #[test]
+    fn test_xdotool_args() {
+        let args = xdotool_args(&parse_keys("ctrl+{enter} ?é").unwrap());
+        assert_eq!(args, vec![
+            "keydown", "ctrl", "keydown", "Return", "keyup", "Return", "keyup", "ctrl",
+            "keydown", "question", "keyup", "question", "keydown", "U00E9", "keyup", "U00E9",
+        ]);
+    }
+
+    #[test]
+    fn test_ydotool_args() {
+        let args = ydotool_args(&parse_keys("ctrl+shift+m A").unwrap()).unwrap();
+        assert_eq!(args, vec![
+            "key", "29:1", "42:1", "50:1", "50:0", "42:0", "29:0",
+            // Uppercase letters hold shift
+            "42:1", "30:1", "30:0", "42:0",
+        ]);
+
+        assert!(ydotool_args(&parse_keys("é").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+//! Interactive "learn button" mode.
+//!
+//! Records the idle traffic of the attached device, asks the user to press
+//! and release a button several times and derives a [`ButtonSignature`] from
+//! the reports that only show up around the presses.
+
+use anyhow::{Context, Result};
+use log::debug;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::{timeout_at, Instant};
+
+use crate::config::{ButtonSignature, Config};
+use crate::hid_monitor::{HidMonitor, MonitorEvent};
+use crate::input::DeviceIdentity;
+
+// How long to record the traffic sent while no button is touched
+const IDLE_DURATION: Duration = Duration::from_secs(3);
+// Number of presses asked from the user
+const LEARN_PRESSES: usize = 5;
+// How long to wait for each press
+const PRESS_TIMEOUT: Duration = Duration::from_secs(15);
+// Reports are collected for this long after the first new report of a press,
+// so the release report is part of the same round
+const PRESS_WINDOW: Duration = Duration::from_millis(1500);
+
+/// Learn the signature of button `name` on the devices watched by `monitor`
+/// and save it to the configuration, replacing a button with the same name
+pub async fn learn_button(name: &str, mut monitor: HidMonitor) -> Result<()> {
+    let mut config = Config::load_or_create_default()?;
+    let mut events = monitor.take_events()
+        .context("HID monitor events already taken")?;
+
+    println!("Learning button '{}'", name);
+    println!("Don't touch the device for {} seconds...", IDLE_DURATION.as_secs());
+
+    let mut idle = Vec::new();
+    let idle_until = Instant::now() + IDLE_DURATION;
+    while let Some((_, data)) = next_report(&mut monitor, &mut events, idle_until).await {
+        idle.push(data);
+    }
+
+    let devices = monitor.connected_devices();
+    if devices.is_empty() {
+        monitor.stop();
+        return Err(anyhow::anyhow!("No SVBONY device connected"));
+    }
+    debug!("Recorded {} idle reports", idle.len());
+
+    let mut learned_device: Option<DeviceIdentity> = None;
+    let mut rounds = Vec::new();
+    for press in 1..=LEARN_PRESSES {
+        println!("Press and release the {} button ({}/{})", name, press, LEARN_PRESSES);
+
+        let mut round = Vec::new();
+        let mut round_until = Instant::now() + PRESS_TIMEOUT;
+        while let Some((device, data)) = next_report(&mut monitor, &mut events, round_until).await {
+            // Only listen to the device the first press came from
+            if learned_device.as_ref().is_some_and(|learned| *learned != device) {
+                continue;
+            }
+
+            if round.is_empty() {
+                if idle.contains(&data) {
+                    continue;
+                }
+                round_until = Instant::now() + PRESS_WINDOW;
+                learned_device.get_or_insert(device);
+            }
+            round.push(data);
+        }
+
+        if round.is_empty() {
+            monitor.stop();
+            return Err(anyhow::anyhow!("No button press detected within {} seconds", PRESS_TIMEOUT.as_secs()));
+        }
+        rounds.push(round);
+    }
+    monitor.stop();
+
+    let signature = derive_signature(name, &idle, &rounds, &config.buttons)?;
+    if let Some(device) = &learned_device {
+        println!("Learned from {}", device);
+    }
+    print_signature(&signature);
+
+    config.buttons.retain(|button| button.name != signature.name);
+    config.buttons.push(signature);
+    config.save()?;
+    println!("Saved button '{}' to {}", name, Config::get_config_path()?.display());
+    println!("Bind actions to it in the \"actions\" section of the configuration file.");
+
+    Ok(())
+}
+
+/// Wait for the next report until `deadline`, keeping devices connected
+async fn next_report(
+    monitor: &mut HidMonitor,
+    events: &mut UnboundedReceiver<MonitorEvent>,
+    deadline: Instant,
+) -> Option<(DeviceIdentity, Vec<u8>)> {
+    loop {
+        let maintenance_delay = monitor.maintain();
+        let wake_at = deadline.min(Instant::now() + maintenance_delay);
+
+        match timeout_at(wake_at, events.recv()).await {
+            Ok(Some(MonitorEvent::Report(device_report))) => {
+                return Some((device_report.device, device_report.report.to_bytes()));
+            }
+            Ok(Some(MonitorEvent::DevicesChanged)) => {}
+            Ok(None) => return None,
+            Err(_) if Instant::now() >= deadline => return None,
+            Err(_) => {}
+        }
+    }
+}
+
+fn print_signature(signature: &ButtonSignature) {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+
+    println!("Report ID: {:02x}", signature.report_id);
+    println!("Offset: {}", signature.offset);
+    println!("Bytes: {}", hex(&signature.bytes));
+    if !signature.mask.is_empty() {
+        println!("Mask: {}", hex(&signature.mask));
+    }
+    if signature.has_release() {
+        println!("Release bytes: {}", hex(&signature.release_bytes));
+    } else {
+        println!("No release report found; long presses cannot be detected");
+    }
+}
+
+/// Derive a signature for button `name` from raw reports (report ID first).
+///
+/// `idle` holds the reports sent while nothing was touched and each entry of
+/// `rounds` the reports around one press. The first report of a round that
+/// never shows up while idle is taken as the press report; payload bytes
+/// that are the same in every press form the signature, bytes that change
+/// between presses are masked out. The first later report with the same
+/// report ID that differs from the press becomes the release report when it
+/// is consistent across rounds.
+///
+/// Fails when the presses disagree, or when the signature is ambiguous: it
+/// also matches idle traffic, unrelated reports seen during the presses, or
+/// reports already claimed by another configured button.
+pub fn derive_signature(
+    name: &str,
+    idle: &[Vec<u8>],
+    rounds: &[Vec<Vec<u8>>],
+    existing: &[ButtonSignature],
+) -> Result<ButtonSignature> {
+    if rounds.len() < 2 {
+        return Err(anyhow::anyhow!("At least two presses are needed to learn a button"));
+    }
+
+    // Index of the press report in each round
+    let mut press_indices = Vec::new();
+    for (round_index, round) in rounds.iter().enumerate() {
+        let press_index = round.iter()
+            .position(|data| !data.is_empty() && !idle.contains(data))
+            .with_context(|| format!("Press {} sent nothing that differs from the idle traffic", round_index + 1))?;
+        press_indices.push(press_index);
+    }
+    let presses: Vec<&Vec<u8>> = rounds.iter().zip(&press_indices)
+        .map(|(round, &index)| &round[index])
+        .collect();
+
+    let report_id = presses[0][0];
+    let length = presses[0].len();
+    if presses.iter().any(|press| press[0] != report_id || press.len() != length) {
+        let ids: Vec<String> = presses.iter().map(|press| format!("{:02x}", press[0])).collect();
+        return Err(anyhow::anyhow!(
+            "Presses produced different reports (report IDs {}); press only the {} button",
+            ids.join(", "), name));
+    }
+
+    // Payload positions whose value is the same in every press
+    let stable: Vec<bool> = (1..length)
+        .map(|i| presses.iter().all(|press| press[i] == presses[0][i]))
+        .collect();
+    let first = stable.iter().position(|&stable| stable)
+        .context("Every payload byte changes between presses")?;
+    let last = stable.iter().rposition(|&stable| stable).unwrap_or(first);
+
+    let press_payload = &presses[0][1..];
+    let mut mask: Vec<u8> = stable[first..=last].iter()
+        .map(|&stable| if stable { 0xff } else { 0x00 })
+        .collect();
+    if mask.iter().all(|&byte| byte == 0xff) {
+        mask.clear();
+    }
+
+    let mut signature = ButtonSignature {
+        name: name.to_string(),
+        report_id,
+        offset: first,
+        bytes: press_payload[first..=last].to_vec(),
+        mask,
+        release_bytes: Vec::new(),
+        usage: None,
+        device: None,
+    };
+
+    // Release: first later report with the same ID that no longer matches
+    let releases: Vec<Option<&Vec<u8>>> = rounds.iter().zip(&press_indices)
+        .map(|(round, &index)| {
+            round[index + 1..].iter().find(|data| {
+                data.len() == length && data[0] == report_id && !signature.matches(report_id, &data[1..])
+            })
+        })
+        .collect();
+    if let Some(Some(release)) = releases.first() {
+        let candidate = release[1 + first..=1 + last].to_vec();
+        let consistent = releases.iter().all(|other| match other {
+            Some(other) => {
+                let mut probe = signature.clone();
+                probe.bytes = candidate.clone();
+                probe.matches(report_id, &other[1..])
+            }
+            None => false,
+        });
+        if consistent {
+            signature.release_bytes = candidate;
+        }
+    }
+
+    // The signature must not fire on anything but the presses
+    if let Some(data) = idle.iter().find(|data| matches_press(&signature, data)) {
+        return Err(anyhow::anyhow!("Ambiguous signature: it also matches idle report {}", to_hex(data)));
+    }
+    for (round, press) in rounds.iter().zip(&presses) {
+        let unrelated = round.iter()
+            .filter(|data| *data != *press)
+            .find(|data| matches_press(&signature, data));
+        if let Some(data) = unrelated {
+            return Err(anyhow::anyhow!("Ambiguous signature: it also matches unrelated report {}", to_hex(data)));
+        }
+    }
+    for other in existing.iter().filter(|other| other.name != name) {
+        if presses.iter().any(|press| other.matches(press[0], &press[1..]) || other.matches_release(press[0], &press[1..])) {
+            return Err(anyhow::anyhow!("The pressed button already matches button '{}'", other.name));
+        }
+    }
+
+    Ok(signature)
+}
+
+fn matches_press(signature: &ButtonSignature, data: &[u8]) -> bool {
+    !data.is_empty() && signature.matches(data[0], &data[1..])
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Periodic status report the device sends while idle
+    const IDLE: &[u8] = &[0x03, 0x00, 0x10, 0x00];
+    const RELEASED: &[u8] = &[0x05, 0x00, 0x00, 0x00];
+
+    fn idle() -> Vec<Vec<u8>> {
+        vec![IDLE.to_vec(), IDLE.to_vec()]
+    }
+
+    fn round(reports: &[&[u8]]) -> Vec<Vec<u8>> {
+        reports.iter().map(|report| report.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_learn_press_and_release() {
+        let rounds = vec![
+            round(&[IDLE, &[0x05, 0x01, 0x00, 0x00], IDLE, RELEASED]),
+            round(&[&[0x05, 0x01, 0x00, 0x00], RELEASED]),
+            round(&[&[0x05, 0x01, 0x00, 0x00], &[0x05, 0x01, 0x00, 0x00], RELEASED, IDLE]),
+        ];
+
+        let signature = derive_signature("Mute", &idle(), &rounds, &Config::default_buttons()).unwrap();
+        assert_eq!(signature.name, "Mute");
+        assert_eq!(signature.report_id, 0x05);
+        assert_eq!(signature.offset, 0);
+        assert_eq!(signature.bytes, vec![0x01, 0x00, 0x00]);
+        assert!(signature.mask.is_empty());
+        assert_eq!(signature.release_bytes, vec![0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_changing_bytes_are_masked() {
+        // Byte 3 is a sequence counter, the last byte is always the same
+        let rounds = vec![
+            round(&[&[0x06, 0x00, 0x02, 0x11, 0x7f]]),
+            round(&[&[0x06, 0x00, 0x02, 0x12, 0x7f]]),
+            round(&[&[0x06, 0x01, 0x02, 0x13, 0x7f]]),
+        ];
+
+        let signature = derive_signature("Call", &idle(), &rounds, &[]).unwrap();
+        assert_eq!(signature.offset, 1);
+        assert_eq!(signature.bytes, vec![0x02, 0x11, 0x7f]);
+        assert_eq!(signature.mask, vec![0xff, 0x00, 0xff]);
+        // Without a release report every press is a click
+        assert!(!signature.has_release());
+        assert!(signature.matches(0x06, &[0x09, 0x02, 0x55, 0x7f]));
+    }
+
+    #[test]
+    fn test_inconsistent_release_is_dropped() {
+        let rounds = vec![
+            round(&[&[0x05, 0x01], &[0x05, 0x00]]),
+            round(&[&[0x05, 0x01], &[0x05, 0x02]]),
+        ];
+
+        let signature = derive_signature("Mute", &idle(), &rounds, &[]).unwrap();
+        assert!(!signature.has_release());
+    }
+
+    #[test]
+    fn test_reject_signature_matching_idle_traffic() {
+        // Only the masked counter differs from the idle report
+        let idle = vec![vec![0x03, 0x00, 0x10, 0x00]];
+        let rounds = vec![
+            round(&[&[0x03, 0x00, 0x10, 0x01]]),
+            round(&[&[0x03, 0x00, 0x10, 0x02]]),
+        ];
+
+        let error = derive_signature("Mute", &idle, &rounds, &[]).unwrap_err();
+        assert!(error.to_string().contains("idle report"));
+    }
+
+    #[test]
+    fn test_reject_signature_matching_unrelated_reports() {
+        let rounds = vec![
+            round(&[&[0x05, 0x01, 0x00], &[0x05, 0x01, 0x01]]),
+            round(&[&[0x05, 0x01, 0x02]]),
+        ];
+
+        let error = derive_signature("Mute", &idle(), &rounds, &[]).unwrap_err();
+        assert!(error.to_string().contains("unrelated report"));
+    }
+
+    #[test]
+    fn test_reject_inconsistent_presses() {
+        let rounds = vec![
+            round(&[&[0x05, 0x01]]),
+            round(&[&[0x06, 0x01]]),
+        ];
+        assert!(derive_signature("Mute", &idle(), &rounds, &[]).is_err());
+
+        // A press that looks like idle traffic
+        let rounds = vec![round(&[&[0x05, 0x01]]), round(&[IDLE])];
+        assert!(derive_signature("Mute", &idle(), &rounds, &[]).is_err());
+
+        assert!(derive_signature("Mute", &idle(), &[round(&[&[0x05, 0x01]])], &[]).is_err());
+    }
+
+    #[test]
+    fn test_reject_button_already_configured() {
+        let ai_press: &[u8] = &[0x04, 0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00];
+        let rounds = vec![round(&[ai_press]), round(&[ai_press])];
+
+        let error = derive_signature("Assistant", &idle(), &rounds, &Config::default_buttons()).unwrap_err();
+        assert!(error.to_string().contains("'AI'"));
+
+        // Re-learning the same button replaces it
+        assert!(derive_signature("AI", &idle(), &rounds, &Config::default_buttons()).is_ok());
+    }
+}
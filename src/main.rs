@@ -1,10 +1,11 @@
 use anyhow::{Context, Result};
-use hidapi::{HidApi, HidDevice};
 use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::time::sleep;
 use tokio::signal;
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
 
 mod config;
 mod hid_monitor;
@@ -12,11 +13,36 @@ mod actions;
 mod cli;
 mod background;
 mod autostart;
+mod input;
+mod gesture;
+mod descriptor;
+mod capture;
+mod learn;
+mod keys;
+mod template;
+mod clipboard;
+mod assistant;
+mod state;
+mod notification;
+mod output;
+mod plugin;
+mod mqtt;
+mod process;
+mod scripting;
+#[cfg(target_os = "linux")]
+mod hotplug;
+#[cfg(target_os = "linux")]
+mod freedesktop;
 
-use config::Config;
-use hid_monitor::HidMonitor;
+use config::{ActionConfig, Config, DeviceFilter, Gesture, MqttTrigger};
+use hid_monitor::{DeviceReport, HidMonitor, MonitorEvent};
 use actions::ActionExecutor;
+use mqtt::MqttClient;
+use gesture::{ButtonEdge, GestureDetector, GestureEvent};
 use cli::CliArgs;
+use capture::CaptureWriter;
+use template::TemplateContext;
+use state::ActionState;
 
 use autostart::AutostartManager;
 
@@ -26,29 +52,68 @@ const VENDOR_ID: u16 = 0xe2b7;
 const M6_PRODUCT_ID: u16 = 0x364d; // SiBiChi
 const OMNI2P_PRODUCT_ID: u16 = 0x5053; // YSAIR
 
-// Expected HID data pattern for AI button
-const AI_BUTTON_PATTERN: &[u8] = &[
-    0x04, 0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+// Model names used in logs and to scope actions to a device
+const SUPPORTED_MODELS: &[(u16, &str)] = &[
+    (M6_PRODUCT_ID, "M6"),
+    (OMNI2P_PRODUCT_ID, "Omni2P"),
 ];
 
+// Extra time after a replay for readers to start and actions to run
+const REPLAY_MARGIN: Duration = Duration::from_millis(500);
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub config: Arc<Mutex<Config>>,
     pub is_running: Arc<Mutex<bool>>,
     pub hid_monitor: Arc<Mutex<Option<HidMonitor>>>,
     pub cli_args: Arc<CliArgs>,
+    /// Set while HID reports are being recorded with --capture
+    pub capture: Arc<Mutex<Option<CaptureWriter>>>,
+    // Wakes up tasks waiting for shutdown
+    shutdown: Arc<watch::Sender<bool>>,
+    // Bumped whenever the configuration is replaced
+    config_reloaded: Arc<watch::Sender<u64>>,
 }
 
 impl AppState {
     pub fn new(cli_args: CliArgs) -> Result<Self> {
         let config = Config::load_or_create_default()?;
+        Ok(Self::with_config(config, cli_args))
+    }
 
-        Ok(AppState {
+    pub fn with_config(config: Config, cli_args: CliArgs) -> Self {
+        AppState {
             config: Arc::new(Mutex::new(config)),
             is_running: Arc::new(Mutex::new(true)),
             hid_monitor: Arc::new(Mutex::new(None)),
             cli_args: Arc::new(cli_args),
-        })
+            capture: Arc::new(Mutex::new(None)),
+            shutdown: Arc::new(watch::channel(false).0),
+            config_reloaded: Arc::new(watch::channel(0).0),
+        }
+    }
+
+    /// Replace the configuration. Whatever actions started keeps running,
+    /// so a toggle that is on can still be switched off by the new
+    /// configuration's action of the same name.
+    pub fn replace_config(&self, config: Config) {
+        *self.config.lock().unwrap() = config;
+        self.config_reloaded.send_modify(|generation| *generation += 1);
+    }
+
+    /// Ask every task to stop
+    pub fn request_shutdown(&self) {
+        {
+            let mut running_guard = self.is_running.lock().unwrap();
+            *running_guard = false;
+        }
+        self.shutdown.send_replace(true);
+    }
+
+    /// Wait until shutdown has been requested
+    pub async fn wait_for_shutdown(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+        let _ = shutdown.wait_for(|requested| *requested).await;
     }
 }
 
@@ -64,7 +129,8 @@ async fn main() -> Result<()> {
                            (!args.contains(&"--foreground".to_string()) && 
                             !args.contains(&"-f".to_string()) && 
                             !args.contains(&"--help".to_string()) &&
-                            !args.contains(&"-h".to_string()));
+                            !args.contains(&"-h".to_string()) &&
+                            !args.iter().any(|arg| ["--capture", "--replay", "--learn"].iter().any(|flag| arg.starts_with(flag))));
         
         if is_background {
             unsafe {
@@ -93,6 +159,12 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    // Learning a button talks to the device, so it is handled here
+    if let Some(name) = &cli_args.learn {
+        learn::learn_button(name, create_device_monitor()?).await?;
+        return Ok(());
+    }
+
     info!("Starting SVBONY AI Assistant v{}", env!("CARGO_PKG_VERSION"));
 
     // Create background service
@@ -163,15 +235,37 @@ async fn main() -> Result<()> {
         info!("Running in foreground mode");
     }
 
-    // Initialize HID monitor with both PIDs
-    let hid_monitor = HidMonitor::new_multi_pid(VENDOR_ID, vec![M6_PRODUCT_ID, OMNI2P_PRODUCT_ID])
-        .context("Failed to initialize HID monitor")?;
+    // Initialize HID monitor for every supported model, or for the
+    // devices of a capture being replayed
+    let hid_monitor = match &app_state.cli_args.replay {
+        Some(path) => {
+            let reports = capture::load_capture(path)?;
+            info!("Replaying {} HID reports from {}", reports.len(), path.display());
+
+            let app_state_for_replay = app_state.clone();
+            let length = capture::capture_length(&reports);
+            tokio::spawn(async move {
+                finish_replay(app_state_for_replay, length).await;
+            });
+
+            let product_ids = SUPPORTED_MODELS.iter().map(|(product_id, _)| *product_id).collect();
+            HidMonitor::with_connector(VENDOR_ID, product_ids, Box::new(capture::replay_connector(&reports)))
+        }
+        None => create_device_monitor()?,
+    };
 
     {
         let mut monitor_guard = app_state.hid_monitor.lock().unwrap();
         *monitor_guard = Some(hid_monitor);
     }
 
+    if let Some(path) = &app_state.cli_args.capture {
+        let writer = CaptureWriter::create(path)?;
+        info!("Recording HID reports to {}", writer.path().display());
+        let mut capture_guard = app_state.capture.lock().unwrap();
+        *capture_guard = Some(writer);
+    }
+
     info!("SVBONY AI Assistant started successfully");
     info!("Monitoring for SVBONY devices (VID: {:04x}, PIDs: [{:04x}, {:04x}])", 
            VENDOR_ID, M6_PRODUCT_ID, OMNI2P_PRODUCT_ID);
@@ -195,19 +289,8 @@ async fn main() -> Result<()> {
     });
 
     // Keep the application running
-    loop {
-        let is_running = {
-            let running_guard = app_state.is_running.lock().unwrap();
-            *running_guard
-        };
-
-        if !is_running {
-            info!("Application shutdown requested");
-            break;
-        }
-
-        sleep(Duration::from_millis(100)).await;
-    }
+    app_state.wait_for_shutdown().await;
+    info!("Application shutdown requested");
 
     // Wait for monitor task to complete
     let _ = monitor_task.await;
@@ -227,13 +310,23 @@ async fn setup_signal_handlers(app_state: AppState) {
             .expect("Failed to setup SIGTERM handler");
         let mut sigint = signal::unix::signal(signal::unix::SignalKind::interrupt())
             .expect("Failed to setup SIGINT handler");
+        let mut sighup = signal::unix::signal(signal::unix::SignalKind::hangup())
+            .expect("Failed to setup SIGHUP handler");
 
-        tokio::select! {
-            _ = sigterm.recv() => {
-                info!("Received SIGTERM, shutting down gracefully");
-            }
-            _ = sigint.recv() => {
-                info!("Received SIGINT, shutting down gracefully");
+        loop {
+            tokio::select! {
+                _ = sigterm.recv() => {
+                    info!("Received SIGTERM, shutting down gracefully");
+                    break;
+                }
+                _ = sigint.recv() => {
+                    info!("Received SIGINT, shutting down gracefully");
+                    break;
+                }
+                _ = sighup.recv() => {
+                    info!("Received SIGHUP, reloading the configuration");
+                    reload_config(&app_state);
+                }
             }
         }
     }
@@ -252,9 +345,54 @@ async fn setup_signal_handlers(app_state: AppState) {
     }
 
     // Signal shutdown
-    {
-        let mut running_guard = app_state.is_running.lock().unwrap();
-        *running_guard = false;
+    app_state.request_shutdown();
+}
+
+/// Load the configuration file again after it was edited. A file that
+/// doesn't load leaves the running configuration alone.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn reload_config(app_state: &AppState) {
+    match Config::get_config_path().and_then(|path| Config::load_from_file(&path)) {
+        Ok(config) => {
+            app_state.replace_config(config);
+            info!("Configuration reloaded");
+        }
+        Err(e) => error!("Failed to reload the configuration, keeping the current one: {:#}", e),
+    }
+}
+
+/// Monitor every attached supported device
+fn create_device_monitor() -> Result<HidMonitor> {
+    let hid_monitor = HidMonitor::new_with_models(VENDOR_ID, SUPPORTED_MODELS)
+        .context("Failed to initialize HID monitor")?;
+
+    #[cfg(target_os = "linux")]
+    let hid_monitor = {
+        let mut hid_monitor = hid_monitor;
+        if let Err(e) = hid_monitor.enable_hotplug() {
+            warn!("Hotplug detection unavailable, falling back to polling: {}", e);
+        }
+        hid_monitor
+    };
+
+    Ok(hid_monitor)
+}
+
+/// Stop the application once a replay of `length` has been played and
+/// pending gestures had time to complete
+async fn finish_replay(app_state: AppState, length: Duration) {
+    let gesture_settle = {
+        let config_guard = app_state.config.lock().unwrap();
+        let gestures = &config_guard.settings.gestures;
+        Duration::from_millis(gestures.multi_press_window_ms.max(gestures.long_press_ms))
+    };
+
+    tokio::select! {
+        _ = tokio::time::sleep(length + gesture_settle + REPLAY_MARGIN) => {
+            info!("Replay finished");
+            app_state.request_shutdown();
+        }
+        _ = app_state.wait_for_shutdown() => {}
     }
 }
 
@@ -290,9 +428,60 @@ async fn setup_autostart_on_first_run(app_state: &AppState) -> Result<()> {
 }
 
 async fn monitor_hid_device(app_state: AppState) {
-    let mut last_connection_attempt = std::time::Instant::now();
-    let mut device: Option<HidDevice> = None;
-    let action_executor = ActionExecutor::new();
+    let mut action_executor = match ActionState::default_path() {
+        Ok(path) => ActionExecutor::with_state(ActionState::load(&path)),
+        Err(e) => {
+            warn!("Action state will not be saved: {}", e);
+            ActionExecutor::new()
+        }
+    };
+
+    let mqtt_settings = app_state.config.lock().unwrap().settings.mqtt.clone();
+    if let Some(settings) = mqtt_settings {
+        match MqttClient::connect(&settings) {
+            Ok(mqtt) => action_executor = action_executor.with_mqtt(mqtt),
+            Err(e) => error!("MQTT is not available: {:#}", e),
+        }
+    }
+    run_monitor_loop(&app_state, &action_executor).await;
+    info!("HID monitoring stopped");
+
+    // Don't leave commands started by actions running
+    action_executor.shutdown().await;
+}
+
+/// Receive reports from the HID monitor stored in `app_state` and dispatch
+/// button gestures until shutdown is requested.
+///
+/// Nothing here polls: the task sleeps until a report arrives, a gesture
+/// timer expires, device maintenance is due or shutdown is requested.
+async fn run_monitor_loop(app_state: &AppState, action_executor: &ActionExecutor) {
+    let mut gesture_detector = {
+        let config_guard = app_state.config.lock().unwrap();
+        GestureDetector::new(&config_guard.settings.gestures, bound_press_counts(&config_guard))
+    };
+    let mut config_reloaded = app_state.config_reloaded.subscribe();
+
+    // Buttons whose signature matched a press and not yet a release, by
+    // device path and button name
+    let mut held_buttons = HashSet::new();
+
+    let mut monitor_events = {
+        let mut monitor_guard = app_state.hid_monitor.lock().unwrap();
+        match monitor_guard.as_mut().and_then(|monitor| monitor.take_events()) {
+            Some(events) => events,
+            None => {
+                warn!("HID monitor is not initialized");
+                return;
+            }
+        }
+    };
+
+    // Without a receiver the branches below never fire
+    let mut notification_clicks = action_executor.take_notification_clicks()
+        .unwrap_or_else(|| tokio::sync::mpsc::unbounded_channel().1);
+    let mut mqtt_triggers = action_executor.take_mqtt_triggers()
+        .unwrap_or_else(|| tokio::sync::mpsc::unbounded_channel().1);
 
     loop {
         let is_running = {
@@ -304,114 +493,565 @@ async fn monitor_hid_device(app_state: AppState) {
             break;
         }
 
-        // Try to connect to device if not connected
-        if device.is_none() && last_connection_attempt.elapsed() > Duration::from_secs(5) {
-            match connect_to_device().await {
-                Ok(hid_device) => {
-                    info!("Connected to SVBONY device");
-                    device = Some(hid_device);
-                }
-                Err(e) => {
-                    debug!("Device not found or connection failed: {}", e);
-                    last_connection_attempt = std::time::Instant::now();
-                }
+        let maintenance_delay = {
+            let mut monitor_guard = app_state.hid_monitor.lock().unwrap();
+            match monitor_guard.as_mut() {
+                Some(monitor) => monitor.maintain(),
+                None => break,
             }
+        };
+
+        let mut wake_at = Instant::now() + maintenance_delay;
+        if let Some(deadline) = gesture_detector.next_deadline() {
+            wake_at = wake_at.min(Instant::from_std(deadline));
         }
 
-        // Monitor device if connected
-        if let Some(ref mut hid_device) = device {
-            match monitor_device_input(hid_device, &app_state, &action_executor).await {
-                Ok(_) => {}
-                Err(e) => {
-                    warn!("Device monitoring error: {}", e);
-                    device = None; // Reset connection on error
-                    last_connection_attempt = std::time::Instant::now();
+        let mut events = Vec::new();
+        tokio::select! {
+            event = monitor_events.recv() => match event {
+                Some(MonitorEvent::Report(device_report)) => {
+                    record_report(&device_report, app_state);
+                    for (button, edge) in report_edges(&device_report, app_state, &mut held_buttons) {
+                        let timestamp = device_report.report.timestamp;
+                        events.extend(gesture_detector.on_edge(&device_report.device, &button, edge, timestamp));
+                    }
                 }
+                Some(MonitorEvent::DevicesChanged) => {}
+                None => break,
+            },
+            Some(name) = notification_clicks.recv() => {
+                dispatch_clicked_action(&name, app_state, action_executor);
+            }
+            Some(trigger) = mqtt_triggers.recv() => {
+                dispatch_mqtt_trigger(&trigger, app_state, action_executor);
             }
+            Ok(()) = config_reloaded.changed() => {
+                let config_guard = app_state.config.lock().unwrap();
+                gesture_detector.reconfigure(&config_guard.settings.gestures, bound_press_counts(&config_guard));
+            }
+            _ = sleep_until(wake_at) => {}
+            _ = app_state.wait_for_shutdown() => break,
         }
+        events.extend(gesture_detector.poll(std::time::Instant::now()));
 
-        sleep(Duration::from_millis(10)).await;
+        for event in &events {
+            dispatch_gesture(event, app_state, action_executor);
+        }
     }
 
-    info!("HID monitoring stopped");
+    // Stop the reader threads
+    let mut monitor_guard = app_state.hid_monitor.lock().unwrap();
+    if let Some(monitor) = monitor_guard.as_mut() {
+        monitor.stop();
+    }
+}
+
+/// Presses the enabled actions of each button wait for
+fn bound_press_counts(config: &Config) -> HashMap<String, u32> {
+    let bindings = config.actions.iter()
+        .filter(|action| action.enabled)
+        .map(|action| (action.button.as_str(), &action.gesture));
+    GestureDetector::max_press_counts(bindings)
+}
+
+/// Append a report to the capture file when --capture is active
+fn record_report(device_report: &DeviceReport, app_state: &AppState) {
+    let mut capture_guard = app_state.capture.lock().unwrap();
+    if let Some(writer) = capture_guard.as_mut() {
+        if let Err(e) = writer.record(device_report) {
+            error!("Failed to record HID report, capture stopped: {:#}", e);
+            *capture_guard = None;
+        }
+    }
 }
 
-async fn connect_to_device() -> Result<HidDevice> {
-    let api = HidApi::new()
-        .context("Failed to initialize HID API")?;
-
-    // Try to connect to M6 first
-    let device = match api.open(VENDOR_ID, M6_PRODUCT_ID) {
-        Ok(device) => {
-            info!("Connected to SVBONY M6 device (PID: {:04x})", M6_PRODUCT_ID);
-            device
-        },
-        Err(_) => {
-            // If M6 not found, try Omni2P
-            match api.open(VENDOR_ID, OMNI2P_PRODUCT_ID) {
-                Ok(device) => {
-                    info!("Connected to SVBONY Omni2P device (PID: {:04x})", OMNI2P_PRODUCT_ID);
-                    device
-                },
-                Err(e) => {
-                    return Err(anyhow::anyhow!("Failed to open any SVBONY device: {}", e));
+/// Translate a report into button edges using the configured signatures
+/// that apply to the device, or else the usages decoded from the device's
+/// report descriptor, renamed by signatures with a `usage`. A release only counts for a button in `held_buttons`, as a release report
+/// also matches while the button was never pressed.
+fn report_edges(device_report: &DeviceReport, app_state: &AppState, held_buttons: &mut HashSet<(String, String)>) -> Vec<(String, ButtonEdge)> {
+    let config_guard = app_state.config.lock().unwrap();
+    let report = &device_report.report;
+    let device = &device_report.device;
+    let signatures: Vec<_> = config_guard.buttons.iter()
+        .filter(|signature| signature.applies_to(device.product_id, &device.model, device.serial_number.as_deref()))
+        .collect();
+    // A byte signature takes over decoded usages of the same name
+    let has_byte_signature = |name: &str| signatures.iter()
+        .any(|signature| signature.usage.is_none() && signature.name.eq_ignore_ascii_case(name));
+
+    let mut edges = Vec::new();
+    for signature in &signatures {
+        if let Some(usage) = &signature.usage {
+            if has_byte_signature(&signature.name) {
+                continue;
+            }
+            // Decoded usages already come in press and release pairs
+            for (_, edge) in device_report.usage_edges.iter().filter(|(button, _)| button.eq_ignore_ascii_case(usage)) {
+                match edge {
+                    ButtonEdge::Pressed => info!("{} button pressed on {}", signature.name, device),
+                    ButtonEdge::Released => debug!("{} button released", signature.name),
                 }
+                edges.push((signature.name.clone(), *edge));
             }
+            continue;
         }
-    };
 
-    // Set non-blocking mode
-    device.set_blocking_mode(false)
-        .context("Failed to set non-blocking mode")?;
+        let key = (device_report.device.path.clone(), signature.name.clone());
+        if report.matches_signature(signature) {
+            if !signature.has_release() {
+                info!("{} button pressed detected!", signature.name);
+                // Without a release report every press is a complete click
+                edges.push((signature.name.clone(), ButtonEdge::Pressed));
+                edges.push((signature.name.clone(), ButtonEdge::Released));
+            } else if held_buttons.insert(key) {
+                info!("{} button pressed detected!", signature.name);
+                edges.push((signature.name.clone(), ButtonEdge::Pressed));
+            }
+        } else if report.matches_release_signature(signature) && held_buttons.remove(&key) {
+            debug!("{} button released", signature.name);
+            edges.push((signature.name.clone(), ButtonEdge::Released));
+        }
+    }
 
-    Ok(device)
+    let decoded = device_report.usage_edges.iter()
+        .filter(|(button, _)| !has_byte_signature(button))
+        .inspect(|(button, edge)| match edge {
+            ButtonEdge::Pressed => info!("{} button pressed on {}", button, device_report.device),
+            ButtonEdge::Released => debug!("{} button released", button),
+        })
+        .cloned();
+    edges.extend(decoded);
+    edges
 }
 
-async fn monitor_device_input(
-    device: &mut HidDevice,
-    app_state: &AppState,
-    action_executor: &ActionExecutor,
-) -> Result<()> {
-    let mut buffer = [0u8; 1024]; // 1K buffer as specified
-
-    match device.read_timeout(&mut buffer, 10) {
-        Ok(size) if size > 0 => {
-            // Log HID data in foreground mode or verbose mode
-            let should_log_data = {
-                let cli_args = &app_state.cli_args;
-                !cli_args.should_run_in_background() || cli_args.verbose
-            };
+fn dispatch_gesture(event: &GestureEvent, app_state: &AppState, action_executor: &ActionExecutor) {
+    debug!("Gesture {:?} on button {} of {} ({} presses)",
+           event.gesture, event.button, event.device, event.press_count);
 
-            if should_log_data {
-                debug!("Received HID data: {} bytes", size);
-                debug!("Data: {:02x?}", &buffer[..std::cmp::min(size, 32)]);
-            }
+    // Execute actions bound to this gesture and device
+    let device = &event.device;
+    let bound_actions = bound_actions(app_state, &event.button, event.gesture, |filter| {
+        filter.matches(device.product_id, &device.model, device.serial_number.as_deref())
+    });
+    run_bound_actions(bound_actions, TemplateContext::for_gesture(event), action_executor);
+}
 
-            // Check if this matches our AI button pattern
-            if size >= AI_BUTTON_PATTERN.len() &&
-               buffer[..AI_BUTTON_PATTERN.len()] == *AI_BUTTON_PATTERN {
-                info!("AI button pressed detected!");
+/// Run the actions bound to the button and gesture of an MQTT trigger.
+/// There is no device, so actions limited to a device don't run.
+fn dispatch_mqtt_trigger(trigger: &MqttTrigger, app_state: &AppState, action_executor: &ActionExecutor) {
+    info!("MQTT message presses {} ({:?})", trigger.button, trigger.gesture);
 
-                // Execute configured actions
-                let config = {
-                    let config_guard = app_state.config.lock().unwrap();
-                    config_guard.clone()
-                };
+    let bound_actions = bound_actions(app_state, &trigger.button, trigger.gesture, |_| false);
+    let context = TemplateContext {
+        button: Some(trigger.button.clone()),
+        gesture: Some(trigger.gesture),
+        press_count: trigger.gesture.press_count(),
+        ..TemplateContext::default()
+    };
+    run_bound_actions(bound_actions, context, action_executor);
+}
 
-                for action in &config.actions {
-                    if let Err(e) = action_executor.execute_action(action).await {
-                        error!("Failed to execute action {}: {}", action.name, e);
-                    }
-                }
+/// Actions bound to `button` and `gesture`, leaving out those limited to a
+/// device that `device_matches` rejects
+fn bound_actions(app_state: &AppState, button: &str, gesture: Gesture, device_matches: impl Fn(&DeviceFilter) -> bool) -> Vec<ActionConfig> {
+    let config_guard = app_state.config.lock().unwrap();
+    config_guard.actions.iter()
+        .filter(|action| action.button.eq_ignore_ascii_case(button))
+        .filter(|action| action.gesture == gesture)
+        .filter(|action| match &action.device {
+            Some(filter) => device_matches(filter),
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+fn run_bound_actions(bound_actions: Vec<ActionConfig>, context: TemplateContext, action_executor: &ActionExecutor) {
+    // Pipelines can take a while, so actions run on their own task and the
+    // monitor loop keeps handling reports. The actions bound to one gesture
+    // still run one after the other, in configuration order.
+    let action_executor = action_executor.clone();
+    tokio::spawn(async move {
+        for action in &bound_actions {
+            if let Err(e) = action_executor.execute_action(action, &context).await {
+                error!("Failed to execute action {}: {:#}", action.name, e);
             }
         }
-        Ok(_) => {
-            // No data available, this is normal in non-blocking mode
+    });
+}
+
+/// Run the action named `name`, whose notification button was clicked
+fn dispatch_clicked_action(name: &str, app_state: &AppState, action_executor: &ActionExecutor) {
+    let action = {
+        let config_guard = app_state.config.lock().unwrap();
+        config_guard.actions.iter().find(|action| action.name == name).cloned()
+    };
+    let Some(action) = action else {
+        warn!("Notification button runs unknown action {}", name);
+        return;
+    };
+
+    info!("Running {} from a notification", name);
+    let action_executor = action_executor.clone();
+    tokio::spawn(async move {
+        if let Err(e) = action_executor.execute_action(&action, &TemplateContext::default()).await {
+            error!("Failed to execute action {}: {:#}", action.name, e);
         }
-        Err(e) => {
-            return Err(anyhow::anyhow!("HID read error: {}", e));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{ActionParameters, ActionType, ButtonSignature, Gesture};
+    use config::DeviceFilter;
+    use input::tests::test_identity;
+    use input::{ScriptStep, ScriptedConnector, ScriptedDevice, ScriptedSource};
+    use tokio::time::sleep;
+
+    // Raw AI button report as sent by the M6 and Omni2P
+    const AI_BUTTON_PATTERN: &[u8] = &[
+        0x04, 0xb2, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+    ];
+
+    fn scripted_app_state(sessions: Vec<ScriptedSource>) -> AppState {
+        let m6 = test_identity("scripted/0", M6_PRODUCT_ID, "M6", Some("M6-0001"));
+        scripted_devices_app_state(vec![ScriptedDevice::new(m6, sessions)])
+    }
+
+    fn scripted_devices_app_state(devices: Vec<ScriptedDevice>) -> AppState {
+        let connector = ScriptedConnector::new(devices);
+        let mut monitor = HidMonitor::with_connector(
+            VENDOR_ID, vec![M6_PRODUCT_ID, OMNI2P_PRODUCT_ID], Box::new(connector));
+        monitor.set_scan_interval(Duration::ZERO);
+
+        let app_state = AppState::with_config(Config::default(), CliArgs::default());
+        *app_state.hid_monitor.lock().unwrap() = Some(monitor);
+        app_state
+    }
+
+    /// Run the monitor loop until `expected` actions were recorded (or a
+    /// timeout passes), then stop it and return the recorded action names
+    async fn run_scripted(app_state: AppState, expected: usize) -> Vec<String> {
+        let recorder = Arc::new(Mutex::new(Vec::new()));
+        let action_executor = ActionExecutor::recording(recorder.clone());
+
+        let stopper = async {
+            let _ = tokio::time::timeout(Duration::from_secs(2), async {
+                while recorder.lock().unwrap().len() < expected {
+                    sleep(Duration::from_millis(5)).await;
+                }
+            }).await;
+            // Give trailing reports a chance to (wrongly) trigger actions
+            sleep(Duration::from_millis(50)).await;
+            app_state.request_shutdown();
+        };
+
+        tokio::join!(run_monitor_loop(&app_state, &action_executor), stopper);
+
+        let recorded = recorder.lock().unwrap().clone();
+        recorded
+    }
+
+    fn default_action_names() -> Vec<String> {
+        Config::default().actions.iter().map(|action| action.name.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_ai_button_report_triggers_configured_actions() {
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("m6", vec![
+                ScriptStep::Idle,
+                ScriptStep::Report(vec![0x04, 0xb3, 0x00, 0x00, 0x01]),
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+                ScriptStep::Report(vec![0x03, 0x00]),
+            ]),
+        ]);
+
+        let recorded = run_scripted(app_state, 2).await;
+        assert_eq!(recorded, default_action_names());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_actions_are_skipped() {
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("m6", vec![ScriptStep::Report(AI_BUTTON_PATTERN.to_vec())]),
+        ]);
+        app_state.config.lock().unwrap().actions[0].enabled = false;
+
+        let recorded = run_scripted(app_state, 1).await;
+        assert_eq!(recorded, default_action_names()[1..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_double_press_runs_only_double_press_actions() {
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("m6", vec![
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+                ScriptStep::Idle,
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+            ]),
+        ]);
+        app_state.config.lock().unwrap().actions[1].gesture = Gesture::Double;
+
+        let recorded = run_scripted(app_state, 1).await;
+        assert_eq!(recorded, default_action_names()[1..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_presses_after_reconnect_are_handled() {
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("first", vec![
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+                ScriptStep::Disconnect,
+            ]),
+            ScriptedSource::new("second", vec![
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+            ]),
+        ]);
+
+        let recorded = run_scripted(app_state, 4).await;
+        assert_eq!(recorded, [default_action_names(), default_action_names()].concat());
+    }
+
+    #[tokio::test]
+    async fn test_actions_scoped_to_device() {
+        let m6 = test_identity("scripted/0", M6_PRODUCT_ID, "M6", Some("M6-0001"));
+        let omni = test_identity("scripted/1", OMNI2P_PRODUCT_ID, "Omni2P", Some("OMNI-0001"));
+        let app_state = scripted_devices_app_state(vec![
+            ScriptedDevice::new(m6, vec![
+                ScriptedSource::new("m6", vec![ScriptStep::Report(AI_BUTTON_PATTERN.to_vec())]),
+            ]),
+            ScriptedDevice::new(omni, vec![
+                ScriptedSource::new("omni", vec![
+                    ScriptStep::Idle,
+                    ScriptStep::Idle,
+                    ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+                ]),
+            ]),
+        ]);
+        {
+            let mut config = app_state.config.lock().unwrap();
+            config.actions[0].device = Some(DeviceFilter {
+                model: Some("omni2p".to_string()),
+                ..DeviceFilter::default()
+            });
+            config.actions[1].device = Some(DeviceFilter {
+                product_id: Some(M6_PRODUCT_ID),
+                serial_number: Some("M6-0001".to_string()),
+                ..DeviceFilter::default()
+            });
         }
+
+        let recorded = run_scripted(app_state, 2).await;
+        let names = default_action_names();
+        assert_eq!(recorded, vec![names[1].clone(), names[0].clone()]);
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_mqtt_trigger_runs_bound_actions() {
+        let app_state = AppState::with_config(Config::default(), CliArgs::default());
+        app_state.config.lock().unwrap().actions[1].device = Some(DeviceFilter {
+            model: Some("m6".to_string()),
+            ..DeviceFilter::default()
+        });
+        let recorder = Arc::new(Mutex::new(Vec::new()));
+        let action_executor = ActionExecutor::recording(recorder.clone());
+
+        let trigger = |gesture| MqttTrigger { topic: "office/desk/button".to_string(), button: "ai".to_string(), gesture };
+        dispatch_mqtt_trigger(&trigger(Gesture::Double), &app_state, &action_executor);
+        dispatch_mqtt_trigger(&trigger(Gesture::Single), &app_state, &action_executor);
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while recorder.lock().unwrap().is_empty() {
+                sleep(Duration::from_millis(5)).await;
+            }
+        }).await.unwrap();
+
+        // Only the action that isn't limited to a device runs
+        assert_eq!(*recorder.lock().unwrap(), vec![default_action_names()[0].clone()]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_toggle_survives_config_reload() {
+        let pid_file = std::env::temp_dir().join(format!("svbony-reload-test-{}.pid", std::process::id()));
+        let recording = |title: &str| ActionConfig {
+            name: "Recording".to_string(),
+            action_type: ActionType::Toggle,
+            parameters: ActionParameters {
+                command: Some("sh".to_string()),
+                args: Some(vec!["-c".to_string(), format!("echo $$ > '{}'; exec sleep 30", pid_file.display())]),
+                title: Some(title.to_string()),
+                ..ActionParameters::default()
+            },
+            ..Config::default().actions[0].clone()
+        };
+        let app_state = AppState::with_config(Config { actions: vec![recording("Recorder")], ..Config::default() }, CliArgs::default());
+        let reloaded = app_state.config_reloaded.subscribe();
+        let action_executor = ActionExecutor::new();
+        let press = || async {
+            let action = bound_actions(&app_state, "AI", Gesture::Single, |_| true).remove(0);
+            action_executor.execute_action(&action, &TemplateContext::default()).await.unwrap();
+        };
+
+        press().await;
+        let pid = loop {
+            if let Some(pid) = std::fs::read_to_string(&pid_file).ok().and_then(|pid| pid.trim().parse().ok()) {
+                break nix::unistd::Pid::from_raw(pid);
+            }
+            sleep(Duration::from_millis(10)).await;
+        };
+        std::fs::remove_file(&pid_file).unwrap();
+
+        // The edited action still switches off the process it started
+        app_state.replace_config(Config { actions: vec![recording("Meeting recorder")], ..Config::default() });
+        assert!(reloaded.has_changed().unwrap());
+        press().await;
+        assert!(nix::sys::signal::kill(pid, None).is_err());
+        assert!(!pid_file.exists());
+    }
+
+    #[tokio::test]
+    async fn test_captured_reports_can_be_replayed() {
+        let capture_path = std::env::temp_dir()
+            .join(format!("svbony-capture-test-{}.txt", std::process::id()));
+
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("m6", vec![
+                ScriptStep::Report(vec![0x03, 0x00]),
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+            ]),
+        ]);
+        *app_state.capture.lock().unwrap() = Some(CaptureWriter::create(&capture_path).unwrap());
+        run_scripted(app_state, 2).await;
+
+        // Every report is recorded, whether or not it matched a button
+        let reports = capture::load_capture(&capture_path).unwrap();
+        std::fs::remove_file(&capture_path).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].data, vec![0x03, 0x00]);
+        assert_eq!(reports[1].data, AI_BUTTON_PATTERN.to_vec());
+        assert_eq!(reports[1].device, test_identity("scripted/0", M6_PRODUCT_ID, "M6", Some("M6-0001")));
+
+        // Replaying the capture runs the same actions again
+        let mut monitor = HidMonitor::with_connector(
+            VENDOR_ID, vec![M6_PRODUCT_ID], Box::new(capture::replay_connector(&reports)));
+        monitor.set_scan_interval(Duration::ZERO);
+        let app_state = AppState::with_config(Config::default(), CliArgs::default());
+        *app_state.hid_monitor.lock().unwrap() = Some(monitor);
+
+        let recorded = run_scripted(app_state, 2).await;
+        assert_eq!(recorded, default_action_names());
+    }
+
+    #[tokio::test]
+    async fn test_replay_keeps_report_timing() {
+        // Field capture: a double press, then a single press after a pause
+        let pressed = "04 b2 00 00 01 00 01 00";
+        let capture = format!("\
+# SVBONY AI Assistant HID capture
+0\te2b7:364d\tM6\t-\t/dev/hidraw3\t{pressed}
+150\te2b7:364d\tM6\t-\t/dev/hidraw3\t{pressed}
+800\te2b7:364d\tM6\t-\t/dev/hidraw3\t{pressed}
+");
+        let reports = capture::parse_capture(&capture).unwrap();
+
+        let monitor = HidMonitor::with_connector(
+            VENDOR_ID, vec![M6_PRODUCT_ID], Box::new(capture::replay_connector(&reports)));
+        let app_state = AppState::with_config(Config::default(), CliArgs::default());
+        *app_state.hid_monitor.lock().unwrap() = Some(monitor);
+        app_state.config.lock().unwrap().actions[1].gesture = Gesture::Double;
+
+        let recorded = run_scripted(app_state, 2).await;
+        let names = default_action_names();
+        assert_eq!(recorded, vec![names[1].clone(), names[0].clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_actions_bound_to_decoded_usages() {
+        let descriptor = include_bytes!("../tests/descriptors/speakerphone.bin");
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("m6", vec![
+                // Consumer control Mute down and up, then the AI button
+                ScriptStep::Report(vec![0x02, 0x04]),
+                ScriptStep::Report(vec![0x02, 0x00]),
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+            ]).with_descriptor(descriptor),
+        ]);
+        app_state.config.lock().unwrap().actions[0].button = "mute".to_string();
+
+        let recorded = run_scripted(app_state, 2).await;
+        let names = default_action_names();
+        assert_eq!(recorded, vec![names[0].clone(), names[1].clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_product_mute_button() {
+        let descriptor = include_bytes!("../tests/descriptors/speakerphone.bin");
+        let app_state = scripted_app_state(vec![
+            ScriptedSource::new("m6", vec![
+                // Telephony Phone Mute down and up, then the AI button
+                ScriptStep::Report(vec![0x01, 0x02]),
+                ScriptStep::Report(vec![0x01, 0x00]),
+                ScriptStep::Report(AI_BUTTON_PATTERN.to_vec()),
+            ]).with_descriptor(descriptor),
+        ]);
+        app_state.config.lock().unwrap().actions[0].button = "Mute".to_string();
+
+        let recorded = run_scripted(app_state, 2).await;
+        let names = default_action_names();
+        assert_eq!(recorded, vec![names[0].clone(), names[1].clone()]);
+    }
+
+    #[test]
+    fn test_product_buttons_are_limited_to_their_devices() {
+        let app_state = scripted_app_state(Vec::new());
+        let edges = |product_id: u16| {
+            let device_report = DeviceReport {
+                device: test_identity("scripted/0", product_id, "M6", None),
+                report: hid_monitor::HidReport { report_id: 0x01, payload: vec![0x01], timestamp: std::time::Instant::now() },
+                usage_edges: vec![("Hook Switch".to_string(), ButtonEdge::Pressed)],
+            };
+            report_edges(&device_report, &app_state, &mut HashSet::new())
+        };
+        let pressed = |button: &str| (button.to_string(), ButtonEdge::Pressed);
+
+        assert_eq!(edges(M6_PRODUCT_ID), vec![pressed("Call"), pressed("Hook Switch")]);
+        assert_eq!(edges(OMNI2P_PRODUCT_ID), vec![pressed("Call"), pressed("Hook Switch")]);
+        assert_eq!(edges(0x1234), vec![pressed("Hook Switch")]);
+    }
+
+    #[test]
+    fn test_release_needs_a_press() {
+        let app_state = scripted_app_state(Vec::new());
+        app_state.config.lock().unwrap().buttons.push(ButtonSignature {
+            name: "Mute".to_string(),
+            report_id: 0x01,
+            offset: 0,
+            bytes: vec![0x04],
+            mask: vec![0x04],
+            release_bytes: vec![0x00],
+            usage: None,
+            device: None,
+        });
+        let device = test_identity("scripted/0", M6_PRODUCT_ID, "M6", None);
+        let mut held_buttons = HashSet::new();
+        let mut edges = |payload: u8| {
+            let device_report = DeviceReport {
+                device: device.clone(),
+                report: hid_monitor::HidReport { report_id: 0x01, payload: vec![payload], timestamp: std::time::Instant::now() },
+                usage_edges: Vec::new(),
+            };
+            report_edges(&device_report, &app_state, &mut held_buttons)
+        };
+        let mute = |edge| vec![("Mute".to_string(), edge)];
+
+        // Reports for other keys leave the bit clear
+        assert!(edges(0x01).is_empty());
+        assert_eq!(edges(0x04), mute(ButtonEdge::Pressed));
+        assert!(edges(0x05).is_empty());
+        assert_eq!(edges(0x01), mute(ButtonEdge::Released));
+        assert!(edges(0x00).is_empty());
+    }
+}
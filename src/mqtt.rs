@@ -0,0 +1,298 @@
+//! Connection to an MQTT broker.
+//!
+//! With `mqtt` in the settings the assistant stays connected to the broker,
+//! reconnecting when the connection is lost. MqttPublish actions publish over
+//! the connection, and a message on a topic of one of the `triggers` runs the
+//! actions bound to the trigger's button and gesture, as if the button had
+//! been pressed.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use rumqttc::tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::{MqttSettings, MqttTls, MqttTrigger};
+
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_TLS_PORT: u16 = 8883;
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+// Pause before reconnecting, so a broker that is down isn't hammered
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Messages waiting to be sent, for example while reconnecting
+const REQUEST_CAPACITY: usize = 64;
+
+#[derive(Clone)]
+pub struct MqttClient {
+    client: AsyncClient,
+    // Triggers whose topic received a message
+    triggered: Arc<Mutex<Option<mpsc::UnboundedReceiver<MqttTrigger>>>>,
+}
+
+impl MqttClient {
+    /// Connect to the broker in `settings` and subscribe to the topics of
+    /// its triggers. The connection is made in the background, so this only
+    /// fails for invalid settings.
+    pub fn connect(settings: &MqttSettings) -> Result<Self> {
+        let options = options(settings)?;
+        let (host, port) = options.broker_address();
+        let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+        let (triggers_sender, triggered) = mpsc::unbounded_channel();
+
+        let subscriber = client.clone();
+        let triggers = settings.triggers.clone();
+        tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker {}:{}", host, port);
+                        // The broker forgets subscriptions with the session
+                        for trigger in &triggers {
+                            if let Err(e) = subscriber.try_subscribe(&trigger.topic, QoS::AtLeastOnce) {
+                                warn!("Failed to subscribe to {}: {}", trigger.topic, e);
+                            }
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        for trigger in triggers.iter().filter(|trigger| rumqttc::matches(&publish.topic, &trigger.topic)) {
+                            debug!("Message on {} presses {} ({:?})", publish.topic, trigger.button, trigger.gesture);
+                            let _ = triggers_sender.send(trigger.clone());
+                        }
+                    }
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection to {}:{} failed: {}", host, port, e);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+            debug!("MQTT connection to {}:{} closed", host, port);
+        });
+
+        Ok(MqttClient {
+            client,
+            triggered: Arc::new(Mutex::new(Some(triggered))),
+        })
+    }
+
+    /// Triggers whose topic received a message. Only the first call gets
+    /// them.
+    pub fn take_triggers(&self) -> Option<mpsc::UnboundedReceiver<MqttTrigger>> {
+        self.triggered.lock().unwrap().take()
+    }
+
+    /// Queue a message; it is sent as soon as the client is connected
+    pub fn publish(&self, topic: &str, payload: Vec<u8>, qos: QoS, retain: bool) -> Result<()> {
+        self.client.try_publish(topic, qos, retain, payload)
+            .with_context(|| format!("Failed to publish on {}", topic))
+    }
+
+    /// Disconnect once the queued messages are sent
+    pub fn disconnect(&self) {
+        if let Err(e) = self.client.try_disconnect() {
+            debug!("Failed to disconnect from the MQTT broker: {}", e);
+        }
+    }
+}
+
+fn options(settings: &MqttSettings) -> Result<MqttOptions> {
+    let default_port = if settings.tls.is_some() { DEFAULT_TLS_PORT } else { DEFAULT_PORT };
+    let client_id = settings.client_id.clone().unwrap_or_else(|| {
+        let host = hostname::get().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        format!("svbony-ai-assistant-{}", host)
+    });
+
+    let mut options = MqttOptions::new(client_id, &settings.host, settings.port.unwrap_or(default_port));
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(username) = &settings.username {
+        options.set_credentials(username, settings.password.as_deref().unwrap_or_default());
+    }
+    if let Some(tls) = &settings.tls {
+        options.set_transport(Transport::tls_with_config(tls_configuration(tls)?));
+    }
+    Ok(options)
+}
+
+fn tls_configuration(tls: &MqttTls) -> Result<TlsConfiguration> {
+    let read = |path: &String| std::fs::read(path).with_context(|| format!("Failed to read {}", path));
+    let client_auth = match (&tls.client_cert_file, &tls.client_key_file) {
+        (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+        (None, None) => None,
+        _ => return Err(anyhow::anyhow!("client_cert_file and client_key_file have to be set together")),
+    };
+
+    match &tls.ca_file {
+        Some(ca_file) => Ok(TlsConfiguration::Simple { ca: read(ca_file)?, alpn: None, client_auth }),
+        None if client_auth.is_none() => system_roots(),
+        None => Err(anyhow::anyhow!("ca_file is required for a client certificate")),
+    }
+}
+
+/// Trust the system's certificates. Unlike rumqttc's default configuration,
+/// certificates that can't be loaded are skipped instead of causing a
+/// panic.
+fn system_roots() -> Result<TlsConfiguration> {
+    let certificates = rustls_native_certs::load_native_certs();
+    for error in &certificates.errors {
+        warn!("Failed to load system certificates: {}", error);
+    }
+
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(certificates.certs);
+    if added == 0 {
+        return Err(anyhow::anyhow!("No system certificates found, set ca_file"));
+    }
+
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    Ok(TlsConfiguration::Rustls(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Gesture;
+    use bytes::BytesMut;
+    use rumqttc::{ConnAck, ConnectReturnCode, PubAck, Publish, SubAck, SubscribeReasonCode};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const MAX_PACKET_SIZE: usize = 64 * 1024;
+
+    /// Just enough of an MQTT broker for one client: it acknowledges what the
+    /// client sends, reports published messages and subscriptions, and sends
+    /// the client the messages given to it
+    struct TestBroker {
+        port: u16,
+        published: mpsc::UnboundedReceiver<Publish>,
+        subscribed: mpsc::UnboundedReceiver<String>,
+        deliver: mpsc::UnboundedSender<Publish>,
+    }
+
+    impl TestBroker {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let (published_sender, published) = mpsc::unbounded_channel();
+            let (subscribed_sender, subscribed) = mpsc::unbounded_channel();
+            let (deliver, mut delivered) = mpsc::unbounded_channel::<Publish>();
+
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = BytesMut::new();
+                loop {
+                    tokio::select! {
+                        packet = read_packet(&mut stream, &mut buffer) => {
+                            let reply = match packet {
+                                Some(Packet::Connect(_)) => Packet::ConnAck(ConnAck::new(ConnectReturnCode::Success, false)),
+                                Some(Packet::Subscribe(subscribe)) => {
+                                    for filter in &subscribe.filters {
+                                        let _ = subscribed_sender.send(filter.path.clone());
+                                    }
+                                    let codes = subscribe.filters.iter().map(|filter| SubscribeReasonCode::Success(filter.qos)).collect();
+                                    Packet::SubAck(SubAck::new(subscribe.pkid, codes))
+                                }
+                                Some(Packet::Publish(publish)) => {
+                                    let pkid = publish.pkid;
+                                    let qos = publish.qos;
+                                    let _ = published_sender.send(publish);
+                                    if qos == QoS::AtMostOnce {
+                                        continue;
+                                    }
+                                    Packet::PubAck(PubAck::new(pkid))
+                                }
+                                Some(Packet::PingReq) => Packet::PingResp,
+                                Some(_) => continue,
+                                None => break,
+                            };
+                            write_packet(&mut stream, &reply).await;
+                        }
+                        Some(publish) = delivered.recv() => {
+                            write_packet(&mut stream, &Packet::Publish(publish)).await;
+                        }
+                    }
+                }
+            });
+
+            TestBroker { port, published, subscribed, deliver }
+        }
+    }
+
+    async fn read_packet(stream: &mut TcpStream, buffer: &mut BytesMut) -> Option<Packet> {
+        loop {
+            match Packet::read(buffer, MAX_PACKET_SIZE) {
+                Ok(packet) => return Some(packet),
+                Err(rumqttc::Error::InsufficientBytes(_)) => {
+                    if stream.read_buf(buffer).await.ok()? == 0 {
+                        return None;
+                    }
+                }
+                Err(e) => panic!("Invalid packet: {}", e),
+            }
+        }
+    }
+
+    async fn write_packet(stream: &mut TcpStream, packet: &Packet) {
+        let mut buffer = BytesMut::new();
+        packet.write(&mut buffer, MAX_PACKET_SIZE).unwrap();
+        stream.write_all(&buffer).await.unwrap();
+    }
+
+    async fn next<T>(receiver: &mut mpsc::UnboundedReceiver<T>) -> T {
+        tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await.unwrap().unwrap()
+    }
+
+    fn settings(port: u16, triggers: Vec<MqttTrigger>) -> MqttSettings {
+        MqttSettings {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            client_id: Some("test".to_string()),
+            username: None,
+            password: None,
+            tls: None,
+            triggers,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_triggers() {
+        let mut broker = TestBroker::start().await;
+        let trigger = MqttTrigger { topic: "office/+/button".to_string(), button: "AI".to_string(), gesture: Gesture::Double };
+        let client = MqttClient::connect(&settings(broker.port, vec![trigger.clone()])).unwrap();
+        let mut triggered = client.take_triggers().unwrap();
+        assert!(client.take_triggers().is_none());
+
+        // Queued before the connection is up
+        client.publish("office/desk/light", b"on".to_vec(), QoS::AtLeastOnce, true).unwrap();
+        let publish = next(&mut broker.published).await;
+        assert_eq!(publish.topic, "office/desk/light");
+        assert_eq!(&publish.payload[..], b"on");
+        assert_eq!(publish.qos, QoS::AtLeastOnce);
+        assert!(publish.retain);
+
+        assert_eq!(next(&mut broker.subscribed).await, "office/+/button");
+        broker.deliver.send(Publish::new("office/hall/light", QoS::AtMostOnce, "ignored")).unwrap();
+        broker.deliver.send(Publish::new("office/desk/button", QoS::AtMostOnce, "pressed")).unwrap();
+        assert_eq!(next(&mut triggered).await, trigger);
+        assert!(triggered.try_recv().is_err());
+
+        assert!(client.publish("office/#", Vec::new(), QoS::AtMostOnce, false).is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let mut settings = settings(1883, Vec::new());
+        settings.port = None;
+        settings.username = Some("desk".to_string());
+        let mqtt_options = options(&settings).unwrap();
+        assert_eq!(mqtt_options.broker_address(), ("127.0.0.1".to_string(), 1883));
+        assert_eq!(mqtt_options.credentials().unwrap().username, "desk");
+
+        settings.tls = Some(MqttTls { client_cert_file: Some("client.pem".to_string()), ..MqttTls::default() });
+        assert!(options(&settings).is_err());
+        settings.tls = Some(MqttTls { ca_file: Some("/nonexistent/ca.pem".to_string()), ..MqttTls::default() });
+        assert!(options(&settings).unwrap_err().to_string().contains("/nonexistent/ca.pem"));
+    }
+}
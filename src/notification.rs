@@ -0,0 +1,169 @@
+//! Command lines for the platform notification tools.
+//!
+//! Titles and messages can come from templates, command output or AI
+//! replies, so they are never pasted into a script as they are. Each backend
+//! gets them encoded for the language it runs: AppleScript string literals
+//! for `osascript`, XML text in PowerShell string literals for Windows
+//! toasts, and plain arguments (after `--`) for `notify-send` and `zenity`.
+
+/// AppleScript string literal, including the quotes
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn applescript_string(text: &str) -> String {
+    let mut literal = String::with_capacity(text.len() + 2);
+    literal.push('"');
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Text escaped for use in an XML text node or attribute (and Pango markup)
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub fn xml_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// PowerShell single-quoted string literal, including the quotes. Nothing is
+/// expanded inside it; the only special characters are the quotes, which
+/// PowerShell also accepts in their typographic forms.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn powershell_string(text: &str) -> String {
+    let mut literal = String::with_capacity(text.len() + 2);
+    literal.push('\'');
+    for c in text.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            literal.push(c);
+        }
+        // Null characters can't be passed on a command line
+        if c != '\0' {
+            literal.push(c);
+        }
+    }
+    literal.push('\'');
+    literal
+}
+
+/// Arguments for `osascript`
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn osascript_args(title: &str, message: &str) -> Vec<String> {
+    vec![
+        "-e".to_string(),
+        format!("display notification {} with title {}", applescript_string(message), applescript_string(title)),
+    ]
+}
+
+/// PowerShell script that shows a Windows toast notification
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn toast_script(title: &str, message: &str) -> String {
+    let toast = format!(
+        "<toast><visual><binding template=\"ToastGeneric\"><text>{}</text><text>{}</text></binding></visual></toast>",
+        xml_text(title),
+        xml_text(message),
+    );
+
+    format!(
+        "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+         [Windows.UI.Notifications.ToastNotification, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+         [Windows.Data.Xml.Dom.XmlDocument, Windows.Data.Xml.Dom.XmlDocument, ContentType = WindowsRuntime] > $null; \
+         $APP_ID = 'SVBONY.AI.Assistant'; \
+         $xml = New-Object Windows.Data.Xml.Dom.XmlDocument; \
+         $xml.LoadXml({}); \
+         $toast = New-Object Windows.UI.Notifications.ToastNotification $xml; \
+         [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier($APP_ID).Show($toast)",
+        powershell_string(&toast),
+    )
+}
+
+/// PowerShell script that shows a balloon tip, for systems without toasts
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn balloon_script(title: &str, message: &str) -> String {
+    format!(
+        "Add-Type -AssemblyName System.Windows.Forms; \
+         $notify = New-Object System.Windows.Forms.NotifyIcon; \
+         $notify.Icon = [System.Drawing.SystemIcons]::Information; \
+         $notify.Visible = $true; \
+         $notify.ShowBalloonTip(3000, {}, {}, [System.Windows.Forms.ToolTipIcon]::Info); \
+         Start-Sleep -Seconds 4; \
+         $notify.Dispose()",
+        powershell_string(title),
+        powershell_string(message),
+    )
+}
+
+/// Arguments for `notify-send`. The `--` keeps a message starting with a
+/// dash from being read as an option.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn notify_send_args(title: &str, message: &str) -> Vec<String> {
+    vec!["--".to_string(), title.to_string(), message.to_string()]
+}
+
+/// Arguments for `zenity`, whose text is Pango markup
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn zenity_args(title: &str, message: &str) -> Vec<String> {
+    vec![
+        "--info".to_string(),
+        format!("--title={}", title),
+        format!("--text={}", xml_text(message)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTILE: &str = "Say \"hi\" to O'Brien & <b>co</b> \\ `whoami` $(rm -rf ~) $env:PATH '@";
+
+    #[test]
+    fn test_osascript_args() {
+        let args = osascript_args("Build \"main\"", HOSTILE);
+        assert_eq!(args[0], "-e");
+        assert_eq!(
+            args[1],
+            r#"display notification "Say \"hi\" to O'Brien & <b>co</b> \\ `whoami` $(rm -rf ~) $env:PATH '@" with title "Build \"main\"""#
+        );
+        assert_eq!(applescript_string("\" & do shell script \"id"), r#""\" & do shell script \"id""#);
+    }
+
+    #[test]
+    fn test_toast_script() {
+        let script = toast_script("It's done", HOSTILE);
+        let expected_xml = "<text>It&apos;s done</text><text>Say &quot;hi&quot; to O&apos;Brien &amp; &lt;b&gt;co&lt;/b&gt; \\ `whoami` $(rm -rf ~) $env:PATH &apos;@</text>";
+        assert!(script.contains(expected_xml), "{}", script);
+        // The XML is a single literal: no quote in the message ends it
+        assert!(script.contains("$xml.LoadXml('<toast>"));
+        assert!(script.contains("</toast>'); $toast"));
+    }
+
+    #[test]
+    fn test_balloon_script() {
+        let script = balloon_script("Done", "It\u{2019}s 'quoted'; Remove-Item C:\\ -Recurse");
+        assert!(script.contains(
+            "ShowBalloonTip(3000, 'Done', 'It\u{2019}\u{2019}s ''quoted''; Remove-Item C:\\ -Recurse', [System.Windows.Forms.ToolTipIcon]::Info)"
+        ));
+    }
+
+    #[test]
+    fn test_linux_args() {
+        assert_eq!(notify_send_args("Title", "--icon=evil"), vec!["--", "Title", "--icon=evil"]);
+        assert_eq!(zenity_args("A & B", "1 < 2"), vec!["--info", "--title=A & B", "--text=1 &lt; 2"]);
+        assert_eq!(xml_text("bell\u{7}tab\t"), "belltab\t");
+    }
+}
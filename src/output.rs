@@ -0,0 +1,196 @@
+//! Capturing what a command prints, for RunCommand actions that show or
+//! store their output.
+//!
+//! Both streams are read while the command runs so it never blocks on a
+//! full pipe. Only the first `limit` bytes of each are kept; the rest is
+//! read and dropped.
+
+use anyhow::{Context, Result};
+use log::debug;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::process::{self, ProcessTracker};
+
+/// Bytes kept per stream unless `max_output_bytes` is set
+pub const DEFAULT_OUTPUT_LIMIT: usize = 4096;
+
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether either stream was longer than the limit
+    pub truncated: bool,
+}
+
+impl CommandOutput {
+    /// What to show for the command: its output when it succeeded, its
+    /// errors (or its output, when it printed no errors) when it failed
+    pub fn summary(&self) -> String {
+        let text = if self.status.success() || self.stderr.trim().is_empty() {
+            &self.stdout
+        } else {
+            &self.stderr
+        };
+
+        let mut summary = text.trim().to_string();
+        if self.truncated {
+            summary.push('…');
+        }
+        summary
+    }
+}
+
+/// Read `reader` to the end, keeping the first `limit` bytes. Returns the
+/// bytes and whether there were more.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, limit: usize) -> std::io::Result<(Vec<u8>, bool)> {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let size = reader.read(&mut buffer).await?;
+        if size == 0 {
+            break;
+        }
+        let room = limit.saturating_sub(kept.len());
+        kept.extend_from_slice(&buffer[..size.min(room)]);
+        truncated |= size > room;
+    }
+
+    Ok((kept, truncated))
+}
+
+/// Run `command` to completion and capture its output, writing `input` to
+/// it first. The command, and whatever it started, is terminated when it
+/// runs or keeps its output open longer than `timeout` or `processes` shuts
+/// down.
+pub async fn capture(command: &mut Command, input: Option<&[u8]>, processes: &ProcessTracker, limit: usize, timeout: Duration, kill_after: Duration) -> Result<CommandOutput> {
+    // Everything the command starts shares its output, and has to stop
+    // with it for the reads below to end
+    process::new_group(command);
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = child.stdout.take().context("Command has no output")?;
+    let stderr = child.stderr.take().context("Command has no error output")?;
+
+    let stdin = child.stdin.take();
+    let write_input = async move {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            // A command may exit without reading all of it
+            if let Err(e) = stdin.write_all(input).await {
+                debug!("Command did not read its input: {}", e);
+            }
+        }
+        // Dropping the pipe closes it, which ends the input
+    };
+
+    // The reads are stopped along with the command, as whatever it left
+    // running in the background can keep the pipes open
+    let output = async {
+        tokio::join!(write_input, read_capped(stdout, limit), read_capped(stderr, limit))
+    };
+    let (status, (_, stdout, stderr)) = processes.wait_with(&mut child, output, Some(timeout), kill_after).await?;
+    let ((stdout, stdout_truncated), (stderr, stderr_truncated)) = (stdout?, stderr?);
+
+    Ok(CommandOutput {
+        status,
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        truncated: stdout_truncated || stderr_truncated,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    async fn capture_shell(script: &str, limit: usize, timeout: Duration) -> Result<CommandOutput> {
+        capture(&mut shell(script), None, &ProcessTracker::new(), limit, timeout, Duration::from_secs(5)).await
+    }
+
+    #[tokio::test]
+    async fn test_capture_output() {
+        let output = capture_shell("echo saved to /tmp/x; echo warning >&2", 100, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.summary(), "saved to /tmp/x");
+        assert_eq!(output.stderr, "warning\n");
+
+        let output = capture_shell("echo partial; echo disk full >&2; exit 2", 100, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(output.summary(), "disk full");
+    }
+
+    #[tokio::test]
+    async fn test_capture_is_capped() {
+        // Far more than a pipe buffer, so the command would block if the
+        // rest were not read
+        let output = capture_shell("yes 0123456789 | head -c 1000000", 16, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, "0123456789\n01234");
+        assert!(output.truncated);
+        assert_eq!(output.summary(), "0123456789\n01234…");
+    }
+
+    #[tokio::test]
+    async fn test_capture_times_out() {
+        let started = std::time::Instant::now();
+        let error = capture_shell("sleep 30", 100, Duration::from_millis(100)).await.unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_background_process_is_stopped_with_output() {
+        // The shell exits right away, the sleep keeps its output open
+        let pid_file = std::env::temp_dir().join(format!("svbony-output-test-{}", std::process::id()));
+        let script = format!("sleep 30 & echo $! > {}; echo started", pid_file.display());
+
+        let started = std::time::Instant::now();
+        let error = capture_shell(&script, 100, Duration::from_millis(500)).await.unwrap_err();
+        assert!(error.to_string().contains("timed out after 500 ms"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let sleep = nix::unistd::Pid::from_raw(std::fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap());
+        let deadline = std::time::Instant::now() + Duration::from_secs(2);
+        while nix::sys::signal::kill(sleep, None).is_ok() && std::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(nix::sys::signal::kill(sleep, None).is_err());
+        std::fs::remove_file(&pid_file).unwrap();
+
+        // Shutdown stops it as well
+        let processes = ProcessTracker::new();
+        let capturing = {
+            let processes = processes.clone();
+            tokio::spawn(async move {
+                capture(&mut shell("sleep 30 & echo started"), None, &processes, 100, Duration::from_secs(60), Duration::from_secs(5)).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let started = std::time::Instant::now();
+        processes.shutdown().await;
+        assert!(capturing.await.unwrap().unwrap_err().to_string().contains("shutdown"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
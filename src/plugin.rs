@@ -0,0 +1,210 @@
+//! External plugins for Plugin actions.
+//!
+//! A plugin is an executable in the `plugins` directory next to the
+//! configuration file, named after the plugin with any extension
+//! (`plugins/jira`, `plugins/jira.py`, `plugins/jira.exe`). For every press
+//! it is started once and speaks a small JSON protocol:
+//!
+//! - The assistant writes one request object to the plugin's input and
+//!   closes it: `{"protocol": 1, "event": {...}, "options": {...}}`, with the
+//!   details of the button event and the action's `plugin_options`.
+//! - The plugin prints one response object and exits:
+//!   `{"protocol": 1, "status": "ok", "message": "...", "requests": [...]}`.
+//!   `status` is `"ok"` or `"error"`; `message` is optional and is the error
+//!   shown for a failure. `requests` lists follow-up requests the assistant
+//!   carries out in order, such as
+//!   `{"type": "notify", "title": "Jira", "message": "Issue created"}`.
+//!
+//! The protocol version is raised for changes old plugins would
+//! misunderstand; new optional fields don't change it. What a plugin prints
+//! to its error output is logged.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// Version of the protocol spoken with plugins
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Directory plugins are looked up in
+pub fn plugins_dir() -> Result<PathBuf> {
+    Ok(Config::get_config_path()?.with_file_name("plugins"))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    path.is_file() && matches!(extension.as_deref(), Some("exe" | "bat" | "cmd" | "com"))
+}
+
+/// Plugins in `dir`, as (name, path) sorted by name. A missing directory has
+/// no plugins.
+pub fn discover(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut plugins: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_executable(path))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            Some((name, path))
+        })
+        .collect();
+    plugins.sort();
+    plugins
+}
+
+/// Executable of the plugin `name`: a path, or the name of a plugin in `dir`
+pub fn find(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.contains(['/', '\\']) {
+        let path = PathBuf::from(name);
+        return if is_executable(&path) {
+            Ok(path)
+        } else {
+            Err(anyhow::anyhow!("Plugin {} is not an executable file", name))
+        };
+    }
+
+    discover(dir)
+        .into_iter()
+        .find(|(plugin, _)| plugin == name)
+        .map(|(_, path)| path)
+        .ok_or_else(|| anyhow::anyhow!("No plugin named {} in {}", name, dir.display()))
+}
+
+/// What the plugin is sent for an `event` with the action's `options`
+pub fn request(event: Value, options: Option<&Value>) -> Value {
+    json!({
+        "protocol": PROTOCOL_VERSION,
+        "event": event,
+        "options": options.cloned().unwrap_or_else(|| json!({})),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginStatus {
+    Ok,
+    Error,
+}
+
+/// Something the plugin asks the assistant to do once it is done
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginRequest {
+    Notify {
+        #[serde(default)]
+        title: Option<String>,
+        message: String,
+    },
+    OpenUrl {
+        url: String,
+    },
+    Copy {
+        text: String,
+    },
+    SendKeys {
+        keys: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginResponse {
+    pub status: PluginStatus,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub requests: Vec<PluginRequest>,
+}
+
+/// Read the response a plugin printed
+pub fn parse_response(output: &str) -> Result<PluginResponse> {
+    let value: Value = serde_json::from_str(output.trim())
+        .context("Plugin did not print a JSON response")?;
+
+    // Checked first, so a newer plugin gets a clear error rather than a
+    // complaint about a field
+    let protocol = value.get("protocol").and_then(Value::as_u64)
+        .context("Plugin response has no protocol version")?;
+    if protocol != PROTOCOL_VERSION as u64 {
+        return Err(anyhow::anyhow!(
+            "Plugin speaks protocol version {}, the assistant speaks version {}", protocol, PROTOCOL_VERSION
+        ));
+    }
+
+    serde_json::from_value(value).context("Invalid plugin response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let response = parse_response(r#"
+            {"protocol": 1, "status": "ok", "message": "Created PROJ-12", "requests": [
+                {"type": "notify", "title": "Jira", "message": "Created PROJ-12"},
+                {"type": "open_url", "url": "https://example.com/PROJ-12"},
+                {"type": "copy", "text": "PROJ-12"}
+            ]}
+        "#).unwrap();
+        assert_eq!(response.status, PluginStatus::Ok);
+        assert_eq!(response.requests, vec![
+            PluginRequest::Notify { title: Some("Jira".to_string()), message: "Created PROJ-12".to_string() },
+            PluginRequest::OpenUrl { url: "https://example.com/PROJ-12".to_string() },
+            PluginRequest::Copy { text: "PROJ-12".to_string() },
+        ]);
+
+        let response = parse_response(r#"{"protocol": 1, "status": "error", "message": "Not logged in"}"#).unwrap();
+        assert_eq!(response.status, PluginStatus::Error);
+        assert!(response.requests.is_empty());
+
+        let error = parse_response(r#"{"protocol": 2, "status": "ok", "future": true}"#).unwrap_err();
+        assert!(error.to_string().contains("protocol version 2"));
+        assert!(parse_response("Traceback (most recent call last):").is_err());
+        assert!(parse_response(r#"{"protocol": 1, "status": "ok", "requests": [{"type": "reboot"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_request() {
+        let request = request(json!({ "button": "AI" }), None);
+        assert_eq!(request, json!({ "protocol": 1, "event": { "button": "AI" }, "options": {} }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discover_and_find() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("svbony-plugins-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, mode) in [("jira.py", 0o755), ("slack", 0o755), ("README.md", 0o644)] {
+            fs::write(dir.join(file), "").unwrap();
+            fs::set_permissions(dir.join(file), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let names: Vec<String> = discover(&dir).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["jira", "slack"]);
+        assert_eq!(find(&dir, "jira").unwrap(), dir.join("jira.py"));
+        assert!(find(&dir, "README").is_err());
+        let path = dir.join("slack").display().to_string();
+        assert_eq!(find(Path::new("/nonexistent"), &path).unwrap(), dir.join("slack"));
+        assert!(discover(&dir.join("missing")).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+//! Supervision of the processes that actions start.
+//!
+//! On Unix every command runs in a process group of its own, so stopping it
+//! also stops whatever it started, such as the programs a shell script runs.
+//! A command is stopped by sending SIGTERM to its group and SIGKILL when it is
+//! still running after a grace period. On Windows only the command itself is
+//! killed.
+//!
+//! Commands waited on through a [`ProcessTracker`] are stopped when their
+//! timeout expires or the tracker shuts down, so they don't outlive the
+//! assistant.
+
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::future::Future;
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::watch;
+
+/// How long a process gets to exit after SIGTERM unless `kill_after_ms` is set
+pub const DEFAULT_KILL_AFTER_MS: u64 = 5_000;
+
+/// Start `command` in a new process group
+pub fn new_group(command: &mut Command) {
+    #[cfg(unix)]
+    command.process_group(0);
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// Ask `child` and its process group to exit, and kill them when they are
+/// still running after `kill_after`
+pub async fn terminate(child: &mut Child, kill_after: Duration) -> Result<ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        use nix::sys::signal::{kill, killpg, Signal};
+        use nix::unistd::Pid;
+
+        let pid = Pid::from_raw(pid as i32);
+        // The child leads its group unless it was started without new_group
+        let signal = |signal| killpg(pid, signal).or_else(|_| kill(pid, signal));
+
+        // SIGTERM lets recorders finish the file they are writing
+        let _ = signal(Signal::SIGTERM);
+        if let Ok(status) = tokio::time::timeout(kill_after, child.wait()).await {
+            let status = status?;
+            debug!("Process {} exited with {}", pid, status);
+            return Ok(status);
+        }
+
+        warn!("Process {} did not exit within {:?}, killing it", pid, kill_after);
+        let _ = signal(Signal::SIGKILL);
+        return Ok(child.wait().await?);
+    }
+
+    #[cfg(not(unix))]
+    let _ = kill_after;
+    child.kill().await?;
+    Ok(child.wait().await?)
+}
+
+/// Ask the rest of the process group led by `pid`, whose leader already
+/// exited, to exit, and kill it when `output` didn't finish after
+/// `kill_after`
+async fn terminate_group<T>(pid: u32, output: impl Future<Output = T>, kill_after: Duration) {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{killpg, Signal};
+        use nix::unistd::Pid;
+
+        let pid = Pid::from_raw(pid as i32);
+        if killpg(pid, Signal::SIGTERM).is_err() {
+            // Nothing is left of the group
+            return;
+        }
+        if tokio::time::timeout(kill_after, output).await.is_err() {
+            warn!("Processes of group {} did not exit within {:?}, killing them", pid, kill_after);
+            let _ = killpg(pid, Signal::SIGKILL);
+        }
+    }
+
+    // Without process groups, only the command itself can be stopped
+    #[cfg(not(unix))]
+    let _ = (pid, output, kill_after);
+}
+
+/// Keeps count of the commands being waited on, so that shutdown can stop
+/// them and wait until they are gone
+#[derive(Clone)]
+pub struct ProcessTracker {
+    /// Set once shutdown has started
+    shutdown: Arc<watch::Sender<bool>>,
+    /// Number of commands being waited on
+    running: Arc<watch::Sender<usize>>,
+}
+
+/// Counts a command as running while it is alive
+struct Running<'a>(&'a watch::Sender<usize>);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.send_modify(|running| *running -= 1);
+    }
+}
+
+impl ProcessTracker {
+    pub fn new() -> Self {
+        ProcessTracker {
+            shutdown: Arc::new(watch::channel(false).0),
+            running: Arc::new(watch::channel(0).0),
+        }
+    }
+
+    /// Wait for `child` to exit. It is terminated when it runs longer than
+    /// `timeout` or the tracker shuts down first, which is reported as an
+    /// error.
+    pub async fn wait(&self, child: &mut Child, timeout: Option<Duration>, kill_after: Duration) -> Result<ExitStatus> {
+        let (status, ()) = self.wait_with(child, std::future::ready(()), timeout, kill_after).await?;
+        Ok(status)
+    }
+
+    /// Wait for `child` to exit and for `output` to finish, such as reading
+    /// the command's output, which only ends once everything the command
+    /// started closed its end of the pipes. When either takes longer than
+    /// `timeout` or the tracker shuts down first, the command's process group
+    /// is terminated, even when the command itself already exited.
+    pub async fn wait_with<T>(&self, child: &mut Child, output: impl Future<Output = T>, timeout: Option<Duration>, kill_after: Duration) -> Result<(ExitStatus, T)> {
+        self.running.send_modify(|running| *running += 1);
+        let _running = Running(&self.running);
+        let mut shutdown = self.shutdown.subscribe();
+        // The id is gone once the child was waited for
+        let pid = child.id();
+
+        let expired = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(output, expired);
+
+        let mut status = None;
+        let mut result = None;
+        let reason = loop {
+            tokio::select! {
+                exited = child.wait(), if status.is_none() => status = Some(exited?),
+                finished = &mut output, if result.is_none() => result = Some(finished),
+                _ = &mut expired => break format!("timed out after {} ms", timeout.unwrap_or_default().as_millis()),
+                _ = shutdown.wait_for(|shutdown| *shutdown) => break "was stopped for shutdown".to_string(),
+            }
+            if let (Some(exited), Some(_)) = (status, &result) {
+                return Ok((exited, result.take().unwrap()));
+            }
+        };
+
+        match (status, pid) {
+            (None, _) => {
+                terminate(child, kill_after).await?;
+            }
+            // What the command left running still holds on to its output
+            (Some(_), Some(pid)) => {
+                debug!("Process {} exited, stopping the rest of its group", pid);
+                terminate_group(pid, output, kill_after).await;
+            }
+            (Some(_), None) => {}
+        }
+        Err(anyhow::anyhow!("Command {}", reason))
+    }
+
+    /// Stop the commands that are still running and wait until they exited.
+    /// Commands waited on afterwards are stopped right away.
+    pub async fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+
+        let mut running = self.running.subscribe();
+        let count = *running.borrow();
+        if count > 0 {
+            info!("Stopping {} running command(s)", count);
+        }
+        let _ = running.wait_for(|running| *running == 0).await;
+    }
+}
+
+impl Default for ProcessTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use std::time::Instant;
+
+    /// Start a shell that runs `script` in a group of its own
+    fn spawn_group(script: &str) -> Child {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]).stdin(Stdio::null());
+        new_group(&mut command);
+        command.spawn().unwrap()
+    }
+
+    fn is_alive(pid: i32) -> bool {
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None).is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_timeout_stops_the_whole_group() {
+        let pid_file = std::env::temp_dir().join(format!("svbony-process-test-{}", std::process::id()));
+        let script = format!("sleep 30 & echo $! > {}; wait", pid_file.display());
+        let mut child = spawn_group(&script);
+
+        let started = Instant::now();
+        let error = ProcessTracker::new()
+            .wait(&mut child, Some(Duration::from_millis(200)), Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out after 200 ms"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The sleep the shell started went with it
+        let grandchild: i32 = std::fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while is_alive(grandchild) && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!is_alive(grandchild));
+
+        std::fs::remove_file(&pid_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_kill_after_grace_period() {
+        // The shell ignores SIGTERM and has to be killed
+        let mut child = spawn_group("trap '' TERM; while true; do sleep 0.05; done");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let started = Instant::now();
+        let status = terminate(&mut child, Duration::from_millis(200)).await.unwrap();
+        assert!(!status.success());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_running_commands() {
+        let tracker = ProcessTracker::new();
+        let waiting = {
+            let tracker = tracker.clone();
+            tokio::spawn(async move {
+                let mut child = spawn_group("sleep 30");
+                tracker.wait(&mut child, None, Duration::from_secs(5)).await
+            })
+        };
+        while *tracker.running.borrow() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let started = Instant::now();
+        tracker.shutdown().await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let error = waiting.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("shutdown"));
+
+        // Later commands don't get to run
+        let mut child = spawn_group("sleep 30");
+        assert!(tracker.wait(&mut child, None, Duration::from_secs(5)).await.is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Embedded Rhai scripts for RhaiScript actions.
+//!
+//! Scripts see the button event as the constant `event`, a map with
+//! `button`, `gesture`, `press_count`, `time`, `timestamp`, `hour`, `minute`,
+//! `weekday` (1 for Monday to 7 for Sunday) and `device`. The assistant's
+//! functions (`open_url`, `run_command`, `notify`, `send_keys`, `http_get`,
+//! `http_post`, `store_get`, `store_set`) are registered by the executor, so
+//! they run and log like the actions of the same name.
+//!
+//! A script may run for a limited number of operations and a limited time,
+//! and its strings, arrays and maps are limited in size, so a mistake in a
+//! script can't hang or exhaust the assistant. Commands and requests a
+//! script waits for get no more than the time it has left.
+
+use anyhow::Result;
+use chrono::{Datelike, Timelike};
+use log::{debug, info};
+use rhai::{Dynamic, Engine, EvalAltResult, Position};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+use crate::template::TemplateContext;
+
+/// Operations a script may run unless `max_operations` is set
+pub const DEFAULT_MAX_OPERATIONS: u64 = 1_000_000;
+/// Milliseconds a script may run unless `timeout_ms` is set
+pub const DEFAULT_SCRIPT_TIMEOUT_MS: u64 = 10_000;
+
+// Bound what a script can allocate
+pub const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 32;
+// Nesting of expressions, at the top level and in functions
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_FUNCTION_EXPR_DEPTH: usize = 32;
+
+pub type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Engine that stops scripts after `max_operations` operations or
+/// `timeout`, with `print` and `debug` going to the log
+pub fn engine(max_operations: u64, timeout: Duration) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(max_operations)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_FUNCTION_EXPR_DEPTH);
+
+    let started = Instant::now();
+    engine.on_progress(move |_| (started.elapsed() > timeout).then(|| Dynamic::from("timeout")));
+    engine.on_print(|text| info!("Script: {}", text));
+    engine.on_debug(|text, _, position| debug!("Script ({}): {}", position, text));
+    engine
+}
+
+/// The `event` map scripts see
+pub fn event_map(context: &TemplateContext) -> ScriptResult<Dynamic> {
+    let mut event = context.event_json();
+    event["hour"] = context.time.hour().into();
+    event["minute"] = context.time.minute().into();
+    event["weekday"] = context.time.weekday().number_from_monday().into();
+    to_dynamic(&event)
+}
+
+pub fn to_dynamic(value: &Value) -> ScriptResult<Dynamic> {
+    rhai::serde::to_dynamic(value)
+}
+
+pub fn to_json(value: &Dynamic) -> ScriptResult<Value> {
+    rhai::serde::from_dynamic(value)
+}
+
+/// Turn a failure of an assistant function into a script error, which the
+/// script can catch with `try`
+pub fn script_error(error: anyhow::Error) -> Box<EvalAltResult> {
+    format!("{:#}", error).into()
+}
+
+/// Time a script has left until `deadline`, or the error that stops it
+pub fn time_left(deadline: Instant) -> ScriptResult<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if !left.is_zero() => Ok(left),
+        _ => Err(EvalAltResult::ErrorTerminated("timeout".into(), Position::NONE).into()),
+    }
+}
+
+/// Describe why a script failed
+pub fn describe_error(error: &EvalAltResult, timeout: Duration) -> String {
+    match error {
+        EvalAltResult::ErrorTerminated(..) => format!("Script ran longer than {} ms", timeout.as_millis()),
+        error => format!("Script failed: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Gesture;
+    use chrono::{Local, TimeZone};
+
+    fn run(engine: &Engine, script: &str) -> ScriptResult<Dynamic> {
+        engine.eval::<Dynamic>(script)
+    }
+
+    #[test]
+    fn test_scripts_are_limited() {
+        let engine = engine(10_000, Duration::from_secs(10));
+        assert_eq!(run(&engine, "let n = 0; for i in 0..10 { n += i } n").unwrap().as_int().unwrap(), 45);
+
+        let error = run(&engine, "loop { }").unwrap_err();
+        assert!(matches!(*error, EvalAltResult::ErrorTooManyOperations(..)));
+        let error = run(&engine, r#"let s = "x"; for i in 0..30 { s += s } s"#).unwrap_err();
+        assert!(matches!(*error, EvalAltResult::ErrorDataTooLarge(..)));
+        let error = run(&engine, "fn f(n) { f(n + 1) } f(0)").unwrap_err();
+        assert!(matches!(*error, EvalAltResult::ErrorInFunctionCall(..) | EvalAltResult::ErrorStackOverflow(..)));
+
+        let timeout = Duration::from_millis(50);
+        let engine = super::engine(u64::MAX, timeout);
+        let error = run(&engine, "loop { }").unwrap_err();
+        assert_eq!(describe_error(&error, timeout), "Script ran longer than 50 ms");
+    }
+
+    #[test]
+    fn test_event_map() {
+        let context = TemplateContext {
+            button: Some("AI".to_string()),
+            gesture: Some(Gesture::Double),
+            press_count: Some(2),
+            // A Wednesday
+            time: Local.with_ymd_and_hms(2024, 5, 8, 18, 30, 0).unwrap(),
+            ..TemplateContext::default()
+        };
+
+        let mut scope = rhai::Scope::new();
+        scope.push_constant("event", event_map(&context).unwrap());
+        let engine = engine(DEFAULT_MAX_OPERATIONS, Duration::from_secs(10));
+        let summary: String = engine.eval_with_scope(&mut scope, r#"
+            let part = if event.hour >= 18 { "evening" } else { "day" };
+            `${event.button} ${event.gesture} x${event.press_count} ${part} ${event.weekday}`
+        "#).unwrap();
+        assert_eq!(summary, "AI Double x2 evening 3");
+
+        let value = serde_json::json!({ "count": 3, "tags": ["a"], "nested": { "ok": true } });
+        assert_eq!(to_json(&to_dynamic(&value).unwrap()).unwrap(), value);
+    }
+}
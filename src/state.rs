@@ -0,0 +1,235 @@
+//! State of actions that outlives a single press, such as whether a toggle
+//! is on or which entry of a cycle runs next.
+//!
+//! The state is keyed by action name, so it is kept when the configuration
+//! is edited, and written to `state.json` next to the configuration file so
+//! it survives a restart. Cycle positions are only written for cycles that
+//! ask for it. Values stored by RhaiScript actions are kept here as well.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StateData {
+    /// Toggle actions that are switched on
+    #[serde(default)]
+    toggles: BTreeMap<String, bool>,
+    /// Positions of cycles that are persisted
+    #[serde(default)]
+    cycles: BTreeMap<String, CyclePosition>,
+    /// Positions of the other cycles
+    #[serde(skip)]
+    session_cycles: BTreeMap<String, CyclePosition>,
+    /// Values scripts stored
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    store: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CyclePosition {
+    /// Entry that runs on the next press
+    next: usize,
+    /// Milliseconds since the Unix epoch of the last press
+    last_used_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ActionState {
+    /// File the state is saved to; kept in memory only when unset
+    path: Option<PathBuf>,
+    data: Mutex<StateData>,
+}
+
+impl ActionState {
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(Config::get_config_path()?.with_file_name("state.json"))
+    }
+
+    /// State that is not saved anywhere
+    pub fn in_memory() -> Self {
+        ActionState::default()
+    }
+
+    /// Load the state saved in `path`. A missing or unreadable file starts
+    /// with every toggle off.
+    pub fn load(path: &Path) -> Self {
+        let data = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid action state in {}: {}", path.display(), e);
+                StateData::default()
+            }),
+            Err(_) => StateData::default(),
+        };
+
+        ActionState {
+            path: Some(path.to_path_buf()),
+            data: Mutex::new(data),
+        }
+    }
+
+    pub fn is_on(&self, name: &str) -> bool {
+        let data = self.data.lock().unwrap();
+        data.toggles.get(name).copied().unwrap_or(false)
+    }
+
+    pub fn set_on(&self, name: &str, on: bool) {
+        let mut data = self.data.lock().unwrap();
+        if on {
+            data.toggles.insert(name.to_string(), true);
+        } else {
+            data.toggles.remove(name);
+        }
+        self.save(&data);
+    }
+
+    /// Index of the entry of the cycle `name` with `len` entries that runs
+    /// now, and move on to the next one. The cycle starts over when it was
+    /// not used for `reset_after`.
+    pub fn advance_cycle(&self, name: &str, len: usize, persist: bool, reset_after: Option<Duration>) -> usize {
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        self.advance_cycle_at(name, len, persist, reset_after, now_ms)
+    }
+
+    fn advance_cycle_at(&self, name: &str, len: usize, persist: bool, reset_after: Option<Duration>, now_ms: u64) -> usize {
+        let mut data = self.data.lock().unwrap();
+        let positions = if persist { &mut data.cycles } else { &mut data.session_cycles };
+        let position = positions.entry(name.to_string()).or_default();
+
+        let idle = reset_after.is_some_and(|reset_after| {
+            now_ms.saturating_sub(position.last_used_ms) >= reset_after.as_millis() as u64
+        });
+        // The list may have become shorter since the position was saved
+        let index = if idle { 0 } else { position.next % len.max(1) };
+        position.next = (index + 1) % len.max(1);
+        position.last_used_ms = now_ms;
+
+        if persist {
+            self.save(&data);
+        }
+        index
+    }
+
+    /// Value a script stored under `key`
+    pub fn stored(&self, key: &str) -> Option<Value> {
+        let data = self.data.lock().unwrap();
+        data.store.get(key).cloned()
+    }
+
+    /// Store `value` under `key` for scripts; null removes the key
+    pub fn store(&self, key: &str, value: Value) {
+        let mut data = self.data.lock().unwrap();
+        if value.is_null() {
+            data.store.remove(key);
+        } else {
+            data.store.insert(key.to_string(), value);
+        }
+        self.save(&data);
+    }
+
+    fn save(&self, data: &StateData) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let result = serde_json::to_string_pretty(data)
+            .context("Failed to serialize action state")
+            .and_then(|content| fs::write(path, content)
+                .with_context(|| format!("Failed to write {}", path.display())));
+        match result {
+            Ok(()) => debug!("Saved action state to {}", path.display()),
+            // Losing the state only affects the next restart
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_state_is_saved() {
+        let path = std::env::temp_dir()
+            .join(format!("svbony-state-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let state = ActionState::load(&path);
+        assert!(!state.is_on("Recording"));
+        state.set_on("Recording", true);
+        state.set_on("Other", true);
+        state.set_on("Other", false);
+
+        let reloaded = ActionState::load(&path);
+        assert!(reloaded.is_on("Recording"));
+        assert!(!reloaded.is_on("Other"));
+
+        fs::write(&path, "not json").unwrap();
+        assert!(!ActionState::load(&path).is_on("Recording"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cycle_wraps_and_resets_when_idle() {
+        let state = ActionState::in_memory();
+        let reset_after = Some(Duration::from_secs(60));
+        let indexes: Vec<usize> = [0, 1000, 2000, 3000]
+            .iter()
+            .map(|&now_ms| state.advance_cycle_at("Tools", 3, false, reset_after, 1_000_000 + now_ms))
+            .collect();
+        assert_eq!(indexes, vec![0, 1, 2, 0]);
+
+        assert_eq!(state.advance_cycle_at("Tools", 3, false, reset_after, 1_005_000), 1);
+        // A minute without presses starts over
+        assert_eq!(state.advance_cycle_at("Tools", 3, false, reset_after, 1_065_000), 0);
+        // Without a reset time the position is kept
+        assert_eq!(state.advance_cycle_at("Tools", 3, false, None, 9_000_000), 1);
+        // A shorter list wraps early
+        assert_eq!(state.advance_cycle_at("Tools", 1, false, None, 9_000_000), 0);
+    }
+
+    #[test]
+    fn test_only_persisted_cycles_are_saved() {
+        let path = std::env::temp_dir()
+            .join(format!("svbony-cycle-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let state = ActionState::load(&path);
+        state.advance_cycle("Saved", 3, true, None);
+        state.advance_cycle("Session", 3, false, None);
+
+        let reloaded = ActionState::load(&path);
+        assert_eq!(reloaded.advance_cycle("Saved", 3, true, None), 1);
+        assert_eq!(reloaded.advance_cycle("Session", 3, false, None), 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_store_is_saved() {
+        let path = std::env::temp_dir()
+            .join(format!("svbony-store-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let state = ActionState::load(&path);
+        assert_eq!(state.stored("count"), None);
+        state.store("count", Value::from(3));
+        state.store("last", Value::from("AI"));
+        state.store("last", Value::Null);
+
+        let reloaded = ActionState::load(&path);
+        assert_eq!(reloaded.stored("count"), Some(Value::from(3)));
+        assert_eq!(reloaded.stored("last"), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
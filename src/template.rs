@@ -0,0 +1,318 @@
+//! Placeholder expansion in action parameters.
+//!
+//! The text parameters of an action (`url`, `command`, `args`, `message`,
+//! `title`, `icon`, `tag`, `prompt`, `model`, `api_key`, `output_file`,
+//! `interpreter`, `stop_command`, `stop_args`, `plugin`, `topic`,
+//! `payload`), webhook headers and the strings in a webhook body and in
+//! plugin options may contain placeholders such as `{{date}}` or
+//! `{{model}}` that are replaced right before the action runs.
+//! Placeholders that take an argument use a colon: `{{date:%d.%m.%Y}}`,
+//! `{{env:USER}}`. Values that are not known for an event, such as the
+//! serial number of a device without one, expand to an empty string;
+//! unknown placeholders are an error.
+
+use anyhow::Result;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::config::{ActionParameters, Gesture};
+use crate::gesture::GestureEvent;
+use crate::input::DeviceIdentity;
+
+/// What is known about the event that triggered an action
+#[derive(Debug, Clone)]
+pub struct TemplateContext {
+    pub button: Option<String>,
+    pub gesture: Option<Gesture>,
+    pub press_count: Option<u32>,
+    pub device: Option<DeviceIdentity>,
+    /// Time the action was triggered; all date placeholders use it
+    pub time: DateTime<Local>,
+}
+
+impl Default for TemplateContext {
+    fn default() -> Self {
+        TemplateContext {
+            button: None,
+            gesture: None,
+            press_count: None,
+            device: None,
+            time: Local::now(),
+        }
+    }
+}
+
+impl TemplateContext {
+    pub fn for_gesture(event: &GestureEvent) -> Self {
+        TemplateContext {
+            button: Some(event.button.clone()),
+            gesture: Some(event.gesture),
+            press_count: Some(event.press_count),
+            device: Some(event.device.clone()),
+            ..TemplateContext::default()
+        }
+    }
+
+    /// The event as sent in the body of a webhook
+    pub fn event_json(&self) -> Value {
+        json!({
+            "button": self.button,
+            "gesture": self.gesture,
+            "press_count": self.press_count,
+            "time": self.time.to_rfc3339(),
+            "timestamp": self.time.timestamp(),
+            "device": self.device.as_ref().map(|device| json!({
+                "model": device.model,
+                "vendor_id": format!("{:04x}", device.vendor_id),
+                "product_id": format!("{:04x}", device.product_id),
+                "serial_number": device.serial_number,
+            })),
+        })
+    }
+
+    /// Environment variables that describe the event to commands and
+    /// scripts
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        [
+            ("SVBONY_BUTTON", "button"),
+            ("SVBONY_GESTURE", "gesture"),
+            ("SVBONY_PRESS_COUNT", "press_count"),
+            ("SVBONY_MODEL", "model"),
+            ("SVBONY_VID", "vid"),
+            ("SVBONY_PID", "pid"),
+            ("SVBONY_SERIAL", "serial"),
+            ("SVBONY_TIMESTAMP", "timestamp"),
+        ]
+        .into_iter()
+        .map(|(variable, name)| (variable, self.value(name, None).unwrap_or_default()))
+        .collect()
+    }
+
+    /// Value of the placeholder `name`, with the text after the colon as
+    /// `argument`
+    fn value(&self, name: &str, argument: Option<&str>) -> Result<String> {
+        let device = self.device.as_ref();
+        let value = match (name, argument) {
+            ("date", None) => self.format_time("%Y-%m-%d")?,
+            ("time", None) => self.format_time("%H-%M-%S")?,
+            ("datetime", None) => self.format_time("%Y-%m-%d_%H-%M-%S")?,
+            ("date", Some(format)) => self.format_time(format)?,
+            ("timestamp", None) => self.time.timestamp().to_string(),
+            ("button", None) => self.button.clone().unwrap_or_default(),
+            ("gesture", None) => self.gesture.map(|gesture| format!("{:?}", gesture)).unwrap_or_default(),
+            ("press_count", None) => self.press_count.map(|count| count.to_string()).unwrap_or_default(),
+            ("model", None) => device.map(|device| device.model.clone()).unwrap_or_default(),
+            ("vid", None) => device.map(|device| format!("{:04x}", device.vendor_id)).unwrap_or_default(),
+            ("pid", None) => device.map(|device| format!("{:04x}", device.product_id)).unwrap_or_default(),
+            ("serial", None) => device.and_then(|device| device.serial_number.clone()).unwrap_or_default(),
+            ("hostname", None) => hostname::get()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ("home", None) => dirs::home_dir()
+                .map(|home| home.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ("env", Some(variable)) => std::env::var(variable).unwrap_or_default(),
+            _ => {
+                let placeholder = match argument {
+                    Some(argument) => format!("{}:{}", name, argument),
+                    None => name.to_string(),
+                };
+                return Err(anyhow::anyhow!("Unknown placeholder '{{{{{}}}}}'", placeholder));
+            }
+        };
+        Ok(value)
+    }
+
+    fn format_time(&self, format: &str) -> Result<String> {
+        // An invalid format would only fail while the date is written
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            return Err(anyhow::anyhow!("Invalid date format '{}'", format));
+        }
+        Ok(self.time.format(format).to_string())
+    }
+}
+
+/// Replace the placeholders in `text`
+pub fn expand(text: &str, context: &TemplateContext) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..].find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unterminated placeholder in '{}'", text))?;
+
+        let placeholder = rest[start + 2..start + end].trim();
+        let (name, argument) = match placeholder.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument)),
+            None => (placeholder, None),
+        };
+        expanded.push_str(&context.value(name, argument)?);
+
+        rest = &rest[start + end + 2..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Expand the placeholders in every parameter that takes them. Key
+/// sequences are left alone since braces are part of their syntax.
+pub fn expand_parameters(parameters: &ActionParameters, context: &TemplateContext) -> Result<ActionParameters> {
+    let expand_option = |value: &Option<String>| value.as_deref()
+        .map(|value| expand(value, context))
+        .transpose();
+
+    Ok(ActionParameters {
+        url: expand_option(&parameters.url)?,
+        command: expand_option(&parameters.command)?,
+        args: parameters.args.as_ref()
+            .map(|args| args.iter().map(|arg| expand(arg, context)).collect::<Result<Vec<String>>>())
+            .transpose()?,
+        keys: parameters.keys.clone(),
+        message: expand_option(&parameters.message)?,
+        title: expand_option(&parameters.title)?,
+        method: parameters.method,
+        headers: parameters.headers.as_ref()
+            .map(|headers| headers.iter()
+                .map(|(name, value)| Ok((name.clone(), expand(value, context)?)))
+                .collect::<Result<BTreeMap<String, String>>>())
+            .transpose()?,
+        body: parameters.body.as_ref().map(|body| expand_json(body, context)).transpose()?,
+        body_format: parameters.body_format,
+        timeout_ms: parameters.timeout_ms,
+        success_status: parameters.success_status.clone(),
+        prompt: expand_option(&parameters.prompt)?,
+        model: expand_option(&parameters.model)?,
+        api_key: expand_option(&parameters.api_key)?,
+        input: parameters.input,
+        copy_reply: parameters.copy_reply,
+        output: parameters.output,
+        output_file: expand_option(&parameters.output_file)?,
+        max_output_bytes: parameters.max_output_bytes,
+        notify_on_error: parameters.notify_on_error,
+        kill_after_ms: parameters.kill_after_ms,
+        detach: parameters.detach,
+        icon: expand_option(&parameters.icon)?,
+        urgency: parameters.urgency,
+        expire_ms: parameters.expire_ms,
+        tag: expand_option(&parameters.tag)?,
+        buttons: parameters.buttons.clone(),
+        plugin: expand_option(&parameters.plugin)?,
+        plugin_options: parameters.plugin_options.as_ref().map(|options| expand_json(options, context)).transpose()?,
+        // Event values reach scripts as environment variables instead, so
+        // they cannot change what the script does
+        script: parameters.script.clone(),
+        interpreter: expand_option(&parameters.interpreter)?,
+        max_operations: parameters.max_operations,
+        topic: expand_option(&parameters.topic)?,
+        payload: expand_option(&parameters.payload)?,
+        qos: parameters.qos,
+        retain: parameters.retain,
+        stop_command: expand_option(&parameters.stop_command)?,
+        stop_args: parameters.stop_args.as_ref()
+            .map(|args| args.iter().map(|arg| expand(arg, context)).collect::<Result<Vec<String>>>())
+            .transpose()?,
+    })
+}
+
+/// Expand the placeholders in every string of a JSON value
+fn expand_json(value: &Value, context: &TemplateContext) -> Result<Value> {
+    Ok(match value {
+        Value::String(text) => Value::String(expand(text, context)?),
+        Value::Array(items) => Value::Array(items.iter()
+            .map(|item| expand_json(item, context))
+            .collect::<Result<Vec<Value>>>()?),
+        Value::Object(fields) => Value::Object(fields.iter()
+            .map(|(name, field)| Ok((name.clone(), expand_json(field, context)?)))
+            .collect::<Result<Map<String, Value>>>()?),
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::tests::test_identity;
+    use chrono::TimeZone;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            button: Some("Mute".to_string()),
+            gesture: Some(Gesture::Double),
+            press_count: Some(2),
+            device: Some(test_identity("/dev/hidraw3", 0x364d, "M6", Some("A1B2"))),
+            time: Local.with_ymd_and_hms(2026, 3, 7, 9, 5, 30).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_expand_event_and_date_placeholders() {
+        let context = context();
+        assert_eq!(
+            expand("https://notes.example/{{date}}?device={{ model }}-{{pid}}-{{serial}}", &context).unwrap(),
+            "https://notes.example/2026-03-07?device=M6-364d-A1B2");
+        assert_eq!(expand("rec_{{datetime}}.mkv", &context).unwrap(), "rec_2026-03-07_09-05-30.mkv");
+        assert_eq!(expand("{{date:%d.%m.%Y %H:%M}}", &context).unwrap(), "07.03.2026 09:05");
+        assert_eq!(expand("{{button}} {{gesture}} x{{press_count}}", &context).unwrap(), "Mute Double x2");
+        assert_eq!(expand("{{timestamp}}", &context).unwrap(), context.time.timestamp().to_string());
+        assert_eq!(expand("no placeholders {}", &context).unwrap(), "no placeholders {}");
+    }
+
+    #[test]
+    fn test_expand_system_placeholders() {
+        let context = TemplateContext::default();
+        std::env::set_var("SVBONY_TEMPLATE_TEST", "value");
+        assert_eq!(expand("{{env:SVBONY_TEMPLATE_TEST}}", &context).unwrap(), "value");
+        assert_eq!(expand("[{{env:SVBONY_TEMPLATE_UNSET}}]", &context).unwrap(), "[]");
+        assert_eq!(expand("{{home}}", &context).unwrap(), dirs::home_dir().unwrap().to_string_lossy());
+
+        // Without a device the device placeholders are empty
+        assert_eq!(expand("[{{model}}{{serial}}]", &context).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_reject_invalid_placeholders() {
+        let context = context();
+        assert!(expand("{{nosuchvalue}}", &context).is_err());
+        assert!(expand("{{model:x}}", &context).is_err());
+        assert!(expand("{{date", &context).is_err());
+        assert!(expand("{{date:%Q}}", &context).is_err());
+    }
+
+    #[test]
+    fn test_keys_are_not_expanded() {
+        let parameters = ActionParameters {
+            url: Some("https://example.com/{{model}}".to_string()),
+            args: Some(vec!["--out".to_string(), "{{date}}.wav".to_string()]),
+            keys: Some("{{enter}}".to_string()),
+            ..ActionParameters::default()
+        };
+
+        let expanded = expand_parameters(&parameters, &context()).unwrap();
+        assert_eq!(expanded.url.as_deref(), Some("https://example.com/M6"));
+        assert_eq!(expanded.args, Some(vec!["--out".to_string(), "2026-03-07.wav".to_string()]));
+        assert_eq!(expanded.keys.as_deref(), Some("{{enter}}"));
+        assert_eq!(expanded.command, None);
+    }
+
+    #[test]
+    fn test_expand_webhook_body_and_headers() {
+        let parameters = ActionParameters {
+            headers: Some(BTreeMap::from([("X-Device".to_string(), "{{model}}".to_string())])),
+            body: Some(json!({ "text": "{{button}} pressed", "count": 2, "tags": ["{{serial}}"] })),
+            ..ActionParameters::default()
+        };
+
+        let expanded = expand_parameters(&parameters, &context()).unwrap();
+        assert_eq!(expanded.headers.unwrap()["X-Device"], "M6");
+        assert_eq!(expanded.body, Some(json!({ "text": "Mute pressed", "count": 2, "tags": ["A1B2"] })));
+
+        let event = context().event_json();
+        assert_eq!(event["gesture"], "Double");
+        assert_eq!(event["device"]["product_id"], "364d");
+        assert_eq!(TemplateContext::default().event_json()["device"], Value::Null);
+    }
+}